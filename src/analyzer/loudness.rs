@@ -18,7 +18,7 @@
 use crate::conversion::Conversion;
 use crate::error::Error;
 use crate::filters::{biquad::Biquad, Filter};
-use crate::frame::FrameIterator;
+use crate::frame::{ChannelMap, FrameIterator};
 use crate::progress::Progress;
 use hound::WavReader;
 use std::collections::VecDeque;
@@ -43,26 +43,55 @@ impl Settings {
         }
     }
 
+    /// Returns the integrative loudness of the input in linear units.
     pub fn analyze<R>(
         &self,
         input: &mut WavReader<R>,
+        layout: &[ChannelMap],
     ) -> Result<Vec<f64>, Error>
+    where
+        R: std::io::Read,
+    {
+        Ok(self
+            .analyze_statistics(input, layout)?
+            .iter()
+            .map(|x| x.integrative)
+            .collect())
+    }
+
+    /// Returns the complete EBU R128 loudness statistics of the input.
+    /// The layout gives the speaker position of every input channel.
+    pub fn analyze_statistics<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+    ) -> Result<Vec<Statistics>, Error>
     where
         R: std::io::Read,
     {
         let spec = input.spec();
         let duration = input.duration();
-        let mut analyzer = if self.channel_independent {
-            vec![
-                Loudness::new(spec.sample_rate as f64, 1);
-                spec.channels as usize
-            ]
-        } else {
-            vec![Loudness::new(
-                spec.sample_rate as f64,
-                spec.channels as usize,
-            )]
-        };
+        if layout.len() != spec.channels as usize {
+            return Err(Error::InvalidArgument(
+                "Channel layout does not match the number of channels.".into(),
+            ));
+        }
+        let fs = spec.sample_rate as f64;
+        // Independent channels keep their BS.1770 weight, the LFE is
+        // skipped in both modes.
+        let (channels, mut analyzer): (Vec<Option<usize>>, Vec<Loudness>) =
+            if self.channel_independent {
+                ChannelMap::weights(layout)
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, weight)| **weight > 0.0)
+                    .map(|(i, weight)| {
+                        (Some(i), Loudness::with_weights(fs, vec![*weight]))
+                    })
+                    .unzip()
+            } else {
+                (vec![None], vec![Loudness::with_layout(fs, layout)])
+            };
 
         let mut progress = Progress::new(duration as usize, "Analyzing sample");
         let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
@@ -70,56 +99,92 @@ impl Settings {
             progress.next();
             match frame {
                 Ok(frame) => {
-                    if self.channel_independent {
-                        for (i, x) in frame.iter().enumerate() {
-                            match analyzer.get_mut(i) {
-                                Some(a) => a.process(&vec![*x])?,
-                                None => return Err(Error::InvalidFrame),
-                            }
+                    for (a, channel) in analyzer.iter_mut().zip(&channels) {
+                        match channel {
+                            Some(i) => a.process(&vec![frame[*i]])?,
+                            None => a.process(frame)?,
                         }
-                    } else {
-                        analyzer[0].process(frame)?
                     }
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        let mut loudness = analyzer
+        let mut statistics = analyzer
             .iter_mut()
-            .map(|x| {
+            .zip(&channels)
+            .map(|(x, channel)| {
                 x.finalize()?;
-                Ok(x.integrative_loudness())
+                Ok(Statistics {
+                    channel: match channel {
+                        Some(i) => format!("ch{}", i + 1),
+                        None => "mix".into(),
+                    },
+                    integrative: x.integrative_loudness(),
+                    momentary_max: x.momentary_max(),
+                    short_term_max: x.short_term_max(),
+                    range: x.loudness_range(),
+                })
             })
-            .collect::<Result<Vec<f64>, Error>>()?;
+            .collect::<Result<Vec<Statistics>, Error>>()?;
         if !self.strict_ebur128 && !self.channel_independent {
-            loudness = loudness
-                .iter()
-                .map(|x| 2.0 * x / spec.channels as f64)
-                .collect();
+            let norm = 2.0 / spec.channels as f64;
+            for x in statistics.iter_mut() {
+                x.integrative *= norm;
+                x.momentary_max *= norm;
+                x.short_term_max *= norm;
+            }
         }
 
-        Ok(loudness)
+        Ok(statistics)
     }
 }
 
+/// EBU R128 loudness statistics of one analyzed channel group.
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    /// Analyzed channel, "ch1", "ch2", ... or "mix" for all channels.
+    pub channel: String,
+    /// Integrative loudness in linear units, i.e. 10^(LUFS/10).
+    pub integrative: f64,
+    /// Maximum momentary (400 ms) loudness in linear units.
+    pub momentary_max: f64,
+    /// Maximum short-term (3 s) loudness in linear units.
+    pub short_term_max: f64,
+    /// Loudness range (LRA) according to EBU Tech 3342 in LU.
+    pub range: f64,
+}
+
 /// EBUR128 loudness analyzer
 #[derive(Debug, Clone)]
 pub struct Loudness {
     /// Number of channels
     channels: usize,
+    /// BS.1770 weight of every channel
+    weights: Vec<f64>,
     /// Weighting channels, one HSF/HPF pair for every channel.
     filter: Vec<[Biquad; 2]>,
-    /// Working ringbuffer
-    buffer: VecDeque<f64>,
-    /// Sample counter for overlap detection
-    counter: usize,
-    /// Size of one block in samples
-    block_size: usize,
-    /// Overlap of the blocks in samples
+    /// Sum of the weighted mean-square values in the current sub-block
+    sub_block_sum: f64,
+    /// Number of samples in the current sub-block
+    sub_block_len: usize,
+    /// Size of one 100 ms sub-block in samples, this is also the
+    /// overlap of the blocks.
     block_overlap: usize,
-    /// Loudness histogram
+    /// Ringbuffer with the sums of the last completed sub-blocks
+    sub_blocks: VecDeque<f64>,
+    /// Current momentary mean-square value (called z_i in EBU R128)
+    momentary: Option<f64>,
+    /// Current short-term mean-square value
+    short_term: Option<f64>,
+    /// Maximum momentary mean-square value
+    momentary_max: f64,
+    /// Maximum short-term mean-square value
+    short_term_max: f64,
+    /// Loudness histogram of the momentary blocks
     histogram: Vec<usize>,
+    /// Loudness histogram of the short-term blocks
+    short_term_histogram: Vec<usize>,
 }
 
 impl Loudness {
@@ -129,9 +194,10 @@ impl Loudness {
     const Z_MAX: f64 = 8.0;
     /// EBU R128 absolute threshold
     const GAMMA_A: f64 = (-70.0 + 0.691) / 10.0;
-
-    /// Channel weights according to EBU R128
-    const CHANNEL_WEIGHT: [f64; 8] = [1.0, 1.0, 1.0, 0.0, 1.4, 1.4, 1.4, 1.4];
+    /// Number of sub-blocks in a momentary block (400 ms)
+    const MOMENTARY_SUB_BLOCKS: usize = 4;
+    /// Number of sub-blocks in a short-term block (3 s)
+    const SHORT_TERM_SUB_BLOCKS: usize = 30;
 
     /// Calculates with k-weighting filters for one channel.
     ///
@@ -173,20 +239,39 @@ impl Loudness {
         [hsf, hpf]
     }
 
+    /// Constructs an analyzer for the default channel layout.
+    #[cfg(test)]
     pub fn new(fs: f64, channels: usize) -> Self {
-        // 400 ms blocks
-        let block_size = (0.4 * fs).ceil() as usize;
-        // 100 ms overlap
+        Self::with_layout(fs, &ChannelMap::default_layout(channels))
+    }
+
+    /// Constructs an analyzer which weights the channels by their
+    /// speaker positions.
+    pub fn with_layout(fs: f64, layout: &[ChannelMap]) -> Self {
+        Self::with_weights(fs, ChannelMap::weights(layout))
+    }
+
+    /// Constructs an analyzer with the given BS.1770 weight of every
+    /// channel.
+    pub fn with_weights(fs: f64, weights: Vec<f64>) -> Self {
+        // 100 ms overlap, blocks are made of multiple sub-blocks.
         let block_overlap = (0.1 * fs).ceil() as usize;
+        let channels = weights.len();
 
         Self {
             channels,
+            weights,
             filter: vec![Self::k_filter(fs); channels],
-            buffer: VecDeque::with_capacity(block_size),
-            counter: 0,
-            block_size,
+            sub_block_sum: 0.0,
+            sub_block_len: 0,
             block_overlap,
+            sub_blocks: VecDeque::with_capacity(Self::SHORT_TERM_SUB_BLOCKS),
+            momentary: None,
+            short_term: None,
+            momentary_max: 0.0,
+            short_term_max: 0.0,
             histogram: vec![0; Self::BIN_COUNT],
+            short_term_histogram: vec![0; Self::BIN_COUNT],
         }
     }
 
@@ -200,112 +285,166 @@ impl Loudness {
         let mut sq_sum: f64 = 0.0;
         for (i, x) in frame.iter().enumerate() {
             // Skip unnecessary calculations for LFE channel.
-            if Self::CHANNEL_WEIGHT[i] == 0.0 {
+            if self.weights[i] == 0.0 {
                 continue;
             }
             // Apply k-weighting filter.
             // True-peak analysis is unnecessary as it does not change the RMS.
             let val = self.filter[i][0].process(*x as f64);
             let val = self.filter[i][1].process(val);
-            sq_sum += Self::CHANNEL_WEIGHT[i] * val * val;
+            sq_sum += self.weights[i] * val * val;
         }
 
-        self.buffer.push_back(sq_sum);
-        self.counter += 1;
+        self.sub_block_sum += sq_sum;
+        self.sub_block_len += 1;
 
-        // Commit every time a new overlapping section starts
-        // and if we have a complete block.
-        if self.counter % self.block_overlap == 0
-            && self.buffer.len() >= self.block_size
-        {
+        // Commit every time a new overlapping section starts.
+        if self.sub_block_len == self.block_overlap {
+            if self.sub_blocks.len() == Self::SHORT_TERM_SUB_BLOCKS {
+                self.sub_blocks.pop_front();
+            }
+            self.sub_blocks.push_back(self.sub_block_sum);
+            self.sub_block_sum = 0.0;
+            self.sub_block_len = 0;
             self.commit_block()?;
         }
         Ok(())
     }
 
-    /// Handle incomplete block if no non-zero block was found.
-    /// This is only necessary for short signals.
+    /// Handle incomplete blocks if no complete block was found.
+    /// This is only necessary for short signals, the whole signal is
+    /// then used as momentary and short-term block.
     pub fn finalize(&mut self) -> Result<(), Error> {
-        if self.histogram.iter().sum::<usize>() == 0 {
-            self.commit_block()?;
+        let len =
+            self.sub_blocks.len() * self.block_overlap + self.sub_block_len;
+        if len == 0 {
+            return Ok(());
+        }
+        let block_sum =
+            self.sub_blocks.iter().sum::<f64>() + self.sub_block_sum;
+        let z = block_sum / len as f64;
+
+        if self.momentary.is_none() {
+            Self::add_to_histogram(&mut self.histogram, z)?;
+            self.momentary_max = z;
+        }
+        if self.short_term.is_none() {
+            self.short_term_max = z;
         }
         Ok(())
     }
 
-    /// Commits a new block to the histogram.
-    /// Incomplete blocks shall be discarded according to the EBU R128
-    /// specification so this usually should not be called if the buffer
-    /// contains incomplete blocks. An exception to this is if the processed
-    /// audio is shorter than one block.
+    /// Commits the latest momentary and short-term blocks to the
+    /// histograms. Incomplete blocks shall be discarded according to the
+    /// EBU R128 specification so blocks are only committed after enough
+    /// sub-blocks were collected.
     fn commit_block(&mut self) -> Result<(), Error> {
-        // Reset counter to avoid overflow, just in case as the actual value
-        // does not matter, only the modulo to block size.
-        self.counter = self.block_size;
-        // Buffer contains mean square values without root
+        // Sub-blocks contain mean square values without root
         // (called z_i in EBU R128).
-        let block_sum: f64 = self.buffer.iter().sum();
+        if self.sub_blocks.len() >= Self::MOMENTARY_SUB_BLOCKS {
+            let block_sum: f64 = self
+                .sub_blocks
+                .iter()
+                .rev()
+                .take(Self::MOMENTARY_SUB_BLOCKS)
+                .sum();
+            let z = block_sum
+                / (Self::MOMENTARY_SUB_BLOCKS * self.block_overlap) as f64;
+            Self::add_to_histogram(&mut self.histogram, z)?;
+            self.momentary = Some(z);
+            self.momentary_max = self.momentary_max.max(z);
+        }
+
+        if self.sub_blocks.len() == Self::SHORT_TERM_SUB_BLOCKS {
+            let block_sum: f64 = self.sub_blocks.iter().sum();
+            let z = block_sum
+                / (Self::SHORT_TERM_SUB_BLOCKS * self.block_overlap) as f64;
+            Self::add_to_histogram(&mut self.short_term_histogram, z)?;
+            self.short_term = Some(z);
+            self.short_term_max = self.short_term_max.max(z);
+        }
+        Ok(())
+    }
 
+    /// Adds a block mean-square value to the given histogram.
+    fn add_to_histogram(histogram: &mut [usize], z: f64) -> Result<(), Error> {
         // Histogram values are simplified log10() immediate values
         // without -0.691 + 10*(...) to safe computing power. This is
         // possible because these constant cancel out anyway during the
         // following processing steps.
-        let block_log = (block_sum / self.buffer.len() as f64).log10();
+        let block_log = z.log10();
 
         // log(block_sum) is within ]-inf, Z_MAX]
         // Get histogram index
-        let idx = (Self::BIN_COUNT as f64 / (Self::Z_MAX - Self::GAMMA_A)
-            * (block_log - Self::GAMMA_A)
-            - 1.0)
-            .round() as isize;
+        let idx = Self::bin_index(block_log);
 
         // If index is out of range, the input is denormalized.
         if idx >= Self::BIN_COUNT as isize {
             return Err(Error::Denormalized);
         // If index is less than zero, the value is below threshold.
         } else if idx >= 0 {
-            self.histogram[idx as usize] += 1;
+            histogram[idx as usize] += 1;
         }
-        self.buffer.drain(..self.block_size);
         Ok(())
     }
 
+    /// Histogram index of a simplified log10() block value.
+    fn bin_index(block_log: f64) -> isize {
+        (Self::BIN_COUNT as f64 / (Self::Z_MAX - Self::GAMMA_A)
+            * (block_log - Self::GAMMA_A)
+            - 1.0)
+            .round() as isize
+    }
+
+    /// Simplified log10() block value of a histogram bin.
+    fn bin_log(idx: usize) -> f64 {
+        (Self::Z_MAX - Self::GAMMA_A) / (Self::BIN_COUNT as f64)
+            * ((idx + 1) as f64)
+            + Self::GAMMA_A
+    }
+
     /// Calculate loudness statistics from histogram.
     /// Returns the accumulated loudness and block count.
-    fn accumulated_loudness(&self, start_idx: usize) -> (f64, usize) {
+    fn accumulated_loudness(
+        histogram: &[usize],
+        start_idx: usize,
+    ) -> (f64, usize) {
         let mut acc_loudness: f64 = 0.0;
         let mut block_count: usize = 0;
 
-        for (i, x) in self.histogram.iter().enumerate().skip(start_idx) {
-            let bin_log = (Self::Z_MAX - Self::GAMMA_A)
-                / (Self::BIN_COUNT as f64)
-                * ((i + 1) as f64)
-                + Self::GAMMA_A;
-            acc_loudness += 10.0_f64.powf(bin_log) * (*x as f64);
+        for (i, x) in histogram.iter().enumerate().skip(start_idx) {
+            acc_loudness += 10.0_f64.powf(Self::bin_log(i)) * (*x as f64);
             block_count += x;
         }
 
         (acc_loudness, block_count)
     }
 
-    /// Returns the integrative loudness of the processed frames in
-    /// linear units, i.e. 10^(LUFS/10).
-    pub fn integrative_loudness(&self) -> f64 {
-        let (acc_loudness, block_count) = self.accumulated_loudness(0);
+    /// Calculates the histogram index of the relative gate.
+    /// The offset is the relative threshold in simplified log10() units,
+    /// e.g. -1 for -10 LU.
+    fn relative_gate(histogram: &[usize], offset: f64) -> usize {
+        let (acc_loudness, block_count) =
+            Self::accumulated_loudness(histogram, 0);
 
         // Calculate gamma_r from histogram.
         // Histogram values are simplified log(x^2) immediate values
         // without -0.691 + 10*(...) to safe computing power. This is
         // possible because they will cancel out anyway.
+        let gamma_r = (acc_loudness / block_count as f64).log10() + offset;
+        Self::bin_index(gamma_r).max(0) as usize
+    }
+
+    /// Returns the integrative loudness of the processed frames in
+    /// linear units, i.e. 10^(LUFS/10).
+    pub fn integrative_loudness(&self) -> f64 {
         // The -1 in the line below is the -10 LUFS from the EBU R128
         // specification without the scaling factor of 10.
-        let gamma_r = (acc_loudness / block_count as f64).log10() - 1.0;
-        let idx_r = ((gamma_r - Self::GAMMA_A) * Self::BIN_COUNT as f64
-            / (Self::Z_MAX - Self::GAMMA_A)
-            - 1.0)
-            .round() as usize;
+        let idx_r = Self::relative_gate(&self.histogram, -1.0);
 
         // Apply Gamma_R threshold and calculate gated loudness (extent).
-        let (acc_loudness, block_count) = self.accumulated_loudness(idx_r);
+        let (acc_loudness, block_count) =
+            Self::accumulated_loudness(&self.histogram, idx_r);
         if block_count == 0 {
             // Silence was processed
             0.0
@@ -314,4 +453,317 @@ impl Loudness {
             0.8529037031 * acc_loudness / block_count as f64
         }
     }
+
+    /// Returns the loudness of the latest momentary (400 ms) block in
+    /// linear units or None if no complete block was processed yet.
+    pub fn momentary_loudness(&self) -> Option<f64> {
+        self.momentary.map(|x| 0.8529037031 * x)
+    }
+
+    /// Returns the loudness of the latest short-term (3 s) block in
+    /// linear units or None if no complete block was processed yet.
+    pub fn short_term_loudness(&self) -> Option<f64> {
+        self.short_term.map(|x| 0.8529037031 * x)
+    }
+
+    /// Returns the maximum momentary loudness in linear units.
+    pub fn momentary_max(&self) -> f64 {
+        0.8529037031 * self.momentary_max
+    }
+
+    /// Returns the maximum short-term loudness in linear units.
+    pub fn short_term_max(&self) -> f64 {
+        0.8529037031 * self.short_term_max
+    }
+
+    /// Returns the loudness range (LRA) of the processed frames in LU
+    /// according to EBU Tech 3342.
+    pub fn loudness_range(&self) -> f64 {
+        // Short-term blocks are gated 20 LU below their mean loudness.
+        let idx_r = Self::relative_gate(&self.short_term_histogram, -2.0);
+        let (_, block_count) =
+            Self::accumulated_loudness(&self.short_term_histogram, idx_r);
+        if block_count == 0 {
+            return 0.0;
+        }
+
+        // LRA is the distance between the 10th and 95th percentiles of
+        // the gated short-term loudness distribution.
+        let low_count = (0.10 * block_count as f64).round() as usize;
+        let high_count = (0.95 * block_count as f64).round() as usize;
+        let mut low_idx = None;
+        let mut high_idx = idx_r;
+        let mut count = 0;
+        for (i, x) in self.short_term_histogram.iter().enumerate().skip(idx_r) {
+            count += x;
+            if low_idx.is_none() && count > low_count {
+                low_idx = Some(i);
+            }
+            if count >= high_count {
+                high_idx = i;
+                break;
+            }
+        }
+
+        let low_idx = low_idx.unwrap_or(high_idx);
+        10.0 * (Self::bin_log(high_idx) - Self::bin_log(low_idx))
+    }
+}
+
+#[test]
+fn test_loudness_stationary_sine() {
+    // EBU Tech 3341 case 1: stereo 1 kHz sine at -23 dBFS
+    let fs = 48000.0;
+    let mut loudness = Loudness::new(fs, 2);
+    for frame in crate::generator::Generator::ebu3341(1, fs).unwrap() {
+        loudness.process(&frame).unwrap();
+    }
+    loudness.finalize().unwrap();
+
+    let to_lufs = |x: f64| 10.0 * x.log10();
+    assert!((to_lufs(loudness.integrative_loudness()) + 23.0).abs() < 0.1);
+    assert!((to_lufs(loudness.momentary_max()) + 23.0).abs() < 0.1);
+    assert!((to_lufs(loudness.short_term_max()) + 23.0).abs() < 0.1);
+    assert!(loudness.loudness_range() < 0.1);
+}
+
+#[test]
+fn test_loudness_short_input() {
+    // 1 s of EBU Tech 3341 case 1 is shorter than a short-term block.
+    let fs = 48000.0;
+    let mut loudness = Loudness::new(fs, 2);
+    for frame in crate::generator::Generator::ebu3341(1, fs)
+        .unwrap()
+        .take(48000)
+    {
+        loudness.process(&frame).unwrap();
+    }
+    loudness.finalize().unwrap();
+
+    let to_lufs = |x: f64| 10.0 * x.log10();
+    assert!((to_lufs(loudness.integrative_loudness()) + 23.0).abs() < 0.1);
+    assert!((to_lufs(loudness.short_term_max()) + 23.0).abs() < 0.1);
+    assert_eq!(loudness.loudness_range(), 0.0);
+}
+
+/// Runs a generated signal through a fresh loudness analyzer.
+#[cfg(test)]
+fn analyze_generator(generator: crate::generator::Generator) -> Loudness {
+    let fs = generator.spec().sample_rate as f64;
+    let mut loudness = Loudness::new(fs, generator.spec().channels as usize);
+    for frame in generator {
+        loudness.process(&frame).unwrap();
+    }
+    loudness.finalize().unwrap();
+    loudness
+}
+
+/// Magnitude response in dB of the ITU-R BS.1770 reference K-filter
+/// coefficients at 48 kHz.
+#[cfg(test)]
+fn reference_k_filter(f: f64) -> f64 {
+    let reference = [
+        Biquad::new(
+            [1.53512485958697, -2.69169618940638, 1.19839281085285],
+            [-1.69065929318241, 0.73248077421585],
+        ),
+        Biquad::new([1.0, -2.0, 1.0], [-1.99004745483398, 0.99007225036621]),
+    ];
+    20.0 * (reference[0].magnitude(48000.0, f)
+        * reference[1].magnitude(48000.0, f))
+    .log10()
+}
+
+#[test]
+fn test_loudness_k_filter() {
+    for fs in [44100.0, 48000.0, 96000.0] {
+        let filter = Loudness::k_filter(fs);
+        for f in [20.0, 50.0, 100.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0] {
+            let response = 20.0
+                * (filter[0].magnitude(fs, f) * filter[1].magnitude(fs, f))
+                    .log10();
+            assert!((response - reference_k_filter(f)).abs() < 0.05);
+        }
+    }
+}
+
+#[test]
+fn test_loudness_ebu3341() {
+    // EBU Tech 3341 cases 1 to 5, integrative loudness +-0.1 LU
+    for fs in [44100.0, 48000.0, 96000.0] {
+        for (case, expected) in
+            [(1, -23.0), (2, -33.0), (3, -23.0), (4, -23.0), (5, -23.0)]
+        {
+            let generator = crate::generator::Generator::ebu3341(case, fs);
+            let loudness = analyze_generator(generator.unwrap());
+            let lufs = 10.0 * loudness.integrative_loudness().log10();
+            assert!((lufs - expected).abs() < 0.1);
+        }
+    }
+}
+
+#[test]
+fn test_loudness_ebu3341_surround() {
+    // EBU Tech 3341 case 6: 5.0 surround, -23 LUFS in strict mode
+    let layout = [
+        ChannelMap::Left,
+        ChannelMap::Right,
+        ChannelMap::Center,
+        ChannelMap::SideLeft,
+        ChannelMap::SideRight,
+    ];
+    let to_lufs = |x: &[Statistics]| -> Vec<f64> {
+        x.iter().map(|x| 10.0 * x.integrative.log10()).collect()
+    };
+    for fs in [44100.0, 48000.0, 96000.0] {
+        let analyze = |settings: Settings| {
+            let generator = crate::generator::Generator::ebu3341(6, fs);
+            let mut wav = generator.unwrap().into_wav();
+            to_lufs(&settings.analyze_statistics(&mut wav, &layout).unwrap())
+        };
+
+        let strict = analyze(Settings::new(false, true));
+        assert!((strict[0] + 23.0).abs() < 0.1);
+
+        // Normalized to stereo by 2/5 channels
+        let normalized = analyze(Settings::new(false, false));
+        assert!((normalized[0] + 23.0 - 10.0 * 0.4_f64.log10()).abs() < 0.1);
+
+        // Every channel as mono signal, a sine is 3 dB below its peak
+        // level. The side channels keep their weight of 1.5 dB.
+        let independent = analyze(Settings::new(true, true));
+        let expected = [-31.01, -31.01, -27.01, -31.52, -31.52];
+        assert_eq!(independent.len(), expected.len());
+        for (x, y) in independent.iter().zip(expected) {
+            assert!((x - y).abs() < 0.1);
+        }
+    }
+}
+
+#[test]
+fn test_loudness_ebu3341_short_term() {
+    // EBU Tech 3341 case 9: constant short-term loudness +-0.1 LU
+    for fs in [44100.0, 48000.0, 96000.0] {
+        let generator = crate::generator::Generator::ebu3341(9, fs).unwrap();
+        let block = (0.1 * fs).ceil() as usize;
+        let mut loudness = Loudness::new(fs, 2);
+        for (i, frame) in generator.enumerate() {
+            loudness.process(&frame).unwrap();
+            if i >= (3.0 * fs) as usize && i % block == 0 {
+                let lufs =
+                    10.0 * loudness.short_term_loudness().unwrap().log10();
+                assert!((lufs + 23.0).abs() < 0.1);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_loudness_ebu3342() {
+    // EBU Tech 3342 cases 1 to 4, loudness range +-1 LU
+    for fs in [44100.0, 48000.0, 96000.0] {
+        for (case, expected) in [(1, 10.0), (2, 5.0), (3, 20.0), (4, 15.0)] {
+            let generator = crate::generator::Generator::ebu3342(case, fs);
+            let loudness = analyze_generator(generator.unwrap());
+            assert!((loudness.loudness_range() - expected).abs() < 1.0);
+        }
+    }
+}
+
+#[test]
+fn test_loudness_bs2217() {
+    use crate::generator::{Generator, Signal};
+
+    // Test cases in the style of ITU-R BS.2217, integrative loudness
+    // +-0.1 LU. A stereo sine with a peak level of X dBFS has X LUFS.
+    let lufs = |generator| {
+        10.0 * analyze_generator(generator).integrative_loudness().log10()
+    };
+    let silent = f64::NEG_INFINITY;
+    for fs in [44100.0, 48000.0, 96000.0] {
+        // Relative gate: the -30 dBFS part is 20 LU below the loud part.
+        // The few blocks across the transition are within the tolerance.
+        let sequence = [(vec![-10.0; 2], 20.0), (vec![-30.0; 2], 20.0)];
+        let loudness = lufs(Generator::sequence(fs, &sequence));
+        assert!((loudness + 10.0).abs() < 0.1, "{} Hz: {}", fs, loudness);
+
+        // Absolute gate: only the part above -70 LUFS is measured.
+        let sequence = [(vec![-69.5; 2], 5.0), (vec![-75.0; 2], 5.0)];
+        let loudness = lufs(Generator::sequence(fs, &sequence));
+        assert!((loudness + 69.5).abs() < 0.1, "{} Hz: {}", fs, loudness);
+
+        // Channel weights of 5.1 with one active channel: L, R, C at unity,
+        // the LFE is excluded and the surround channels are 1.5 dB louder.
+        // The mono sine is 3 dB below its peak level.
+        let expected = [-23.01, -23.01, -23.01, silent, -21.52, -21.52];
+        for (channel, expected) in expected.iter().enumerate() {
+            let mut levels = vec![silent; 6];
+            levels[channel] = -20.0;
+            let loudness = lufs(Generator::sequence(fs, &[(levels, 5.0)]));
+            if expected.is_finite() {
+                assert!((loudness - expected).abs() < 0.1);
+            } else {
+                assert_eq!(loudness, silent);
+            }
+        }
+
+        // Frequency response of the K-filter: the loudness of a stereo sine
+        // follows the reference filter, which amplifies 1 kHz by 0.691 dB.
+        for f in [25.0, 100.0, 500.0, 1000.0, 2000.0, 10000.0] {
+            let signal = Signal::Sine {
+                frequency: f,
+                phase: 0.0,
+            };
+            let generator =
+                Generator::new(&signal, fs, 2, -20.0, 5 * fs as usize);
+            let loudness = lufs(generator.unwrap());
+            let expected = -20.691 + reference_k_filter(f);
+            assert!(
+                (loudness - expected).abs() < 0.1,
+                "{} Hz, {} Hz: {} LUFS",
+                fs,
+                f,
+                loudness
+            );
+        }
+    }
+}
+
+#[test]
+fn test_loudness_lfe() {
+    use crate::generator::{Generator, Signal};
+
+    // 5.1 sine at -20 dBFS, the LFE is neither measured nor reported.
+    let fs = 48000.0;
+    let layout = ChannelMap::default_layout(6);
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    for independent in [false, true] {
+        let mut wav = Generator::new(&signal, fs, 6, -20.0, 48000)
+            .unwrap()
+            .into_wav();
+        let settings = Settings::new(independent, true);
+        let statistics =
+            settings.analyze_statistics(&mut wav, &layout).unwrap();
+        let channels: Vec<&str> =
+            statistics.iter().map(|x| x.channel.as_str()).collect();
+        let loudness: Vec<f64> = statistics
+            .iter()
+            .map(|x| 10.0 * x.integrative.log10())
+            .collect();
+        if independent {
+            assert_eq!(channels, ["ch1", "ch2", "ch3", "ch5", "ch6"]);
+            let expected = [-23.01, -23.01, -23.01, -21.52, -21.52];
+            for (x, y) in loudness.iter().zip(expected) {
+                assert!((x - y).abs() < 0.1, "{} {}", x, y);
+            }
+        } else {
+            // L, R and C with unity and Ls, Rs with 1.41 weight
+            let expected = 10.0 * (3.0 + 2.0 * 1.41_f64).log10() - 23.01;
+            assert_eq!(channels, ["mix"]);
+            assert!((loudness[0] - expected).abs() < 0.1);
+        }
+    }
 }
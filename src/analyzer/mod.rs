@@ -17,4 +17,8 @@
 \******************************************************************************/
 pub mod loudness;
 pub mod rms;
+pub mod silence;
+pub mod spectrogram;
+pub mod spectrum;
+pub mod time_series;
 pub mod true_peak;
@@ -30,12 +30,6 @@ pub struct Settings {
 }
 
 impl Settings {
-    pub fn new(channel_independent: bool) -> Self {
-        Self {
-            channel_independent,
-        }
-    }
-
     pub fn analyze<R>(
         &self,
         input: &mut WavReader<R>,
@@ -110,6 +104,12 @@ impl Rms {
         Ok(())
     }
 
+    /// Reset the cumulative RMS, e.g. to start a new analysis window.
+    pub fn reset(&mut self) {
+        self.counter = 0;
+        self.sq_sum = KahanSum::new();
+    }
+
     /// Returns the root-mean-square value of the processed audio in
     /// linear units.
     pub fn rms(&self) -> f64 {
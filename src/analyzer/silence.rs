@@ -0,0 +1,270 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::loudness::Settings as Lufs;
+use crate::conversion::Conversion;
+use crate::effects::compressor::PeakDetector;
+use crate::envelope::Position;
+use crate::error::Error;
+use crate::filters::{mov_max::MovMax, mov_rms::MovRms, Filter};
+use crate::frame::{ChannelMap, FrameIterator};
+use crate::progress::Progress;
+use crate::report::Format;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args)]
+#[command(allow_negative_numbers = true)]
+pub struct Settings {
+    /// Silence threshold in dBFS, or in LU relative to the integrative
+    /// loudness with --relative
+    #[arg(long, default_value_t = -60.0)]
+    threshold: f64,
+    /// Threshold is relative to the integrative loudness
+    #[arg(long)]
+    relative: bool,
+    /// Minimum duration of silent regions in seconds or with unit suffix
+    /// s, ms or samples. Silence at the beginning and end is always found.
+    #[arg(long, default_value = "0.5")]
+    min_duration: Position,
+    /// Level detector
+    #[arg(long, value_enum, default_value_t = PeakDetector::Rms)]
+    detector: PeakDetector,
+    /// Detector window length
+    #[arg(long, default_value = "10ms")]
+    window: Position,
+}
+
+/// Range of samples, the end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Settings {
+    /// Constructs peak detector settings with a 10 ms window.
+    #[cfg(test)]
+    pub fn new(threshold: f64, min_duration: Position) -> Self {
+        Self {
+            threshold,
+            relative: false,
+            min_duration,
+            detector: PeakDetector::Peak,
+            window: Position::Seconds(0.01),
+        }
+    }
+
+    /// Returns the silent regions of the input, where the level of all
+    /// channels is below the threshold.
+    pub fn analyze<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+    ) -> Result<Vec<Region>, Error>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let spec = input.spec();
+        let fs = spec.sample_rate as f64;
+        let duration = input.duration() as usize;
+        let threshold_db = if self.relative {
+            let loudness = Lufs::new(false, false).analyze(input, layout)?[0];
+            input.seek(0)?;
+            10.0 * loudness.log10() + self.threshold
+        } else {
+            self.threshold
+        };
+        let threshold = 10.0_f64.powf(threshold_db / 20.0);
+        let window = self.window.samples(fs).max(1);
+        let min_duration = self.min_duration.samples(fs);
+
+        let mut detectors: Vec<Box<dyn Filter>> = (0..spec.channels)
+            .map(|_| match self.detector {
+                PeakDetector::Peak => {
+                    Box::new(MovMax::new(window)) as Box<dyn Filter>
+                }
+                PeakDetector::Rms => Box::new(MovRms::new(1.0, window)),
+            })
+            .collect();
+
+        let mut regions = Vec::new();
+        let mut push = |start: usize, end: usize| {
+            // The detector output at n covers the samples from
+            // n - window + 1 to n.
+            let start = (start + 1).saturating_sub(window);
+            if end - start >= min_duration || start == 0 || end == duration {
+                regions.push(Region { start, end });
+            }
+        };
+
+        let mut start = None;
+        let mut index = 0;
+        let mut progress = Progress::new(duration, "Analyzing sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        while let Some(frame) = frames.next() {
+            progress.next();
+            let level = frame?
+                .iter()
+                .zip(detectors.iter_mut())
+                .map(|(x, detector)| detector.process(x.abs() as f64))
+                .fold(0.0, f64::max);
+            match (level < threshold, start) {
+                (true, None) => start = Some(index),
+                (false, Some(x)) => {
+                    push(x, index);
+                    start = None;
+                }
+                _ => (),
+            }
+            index += 1;
+        }
+        if let Some(x) = start {
+            push(x, index);
+        }
+
+        Ok(regions)
+    }
+
+    /// Writes the regions as cue list. The text format is an Audacity
+    /// label track with tab separated start and end time and a label.
+    pub fn write<W>(
+        regions: &[Region],
+        fs: f64,
+        format: Format,
+        output: &mut W,
+    ) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        let seconds = |x: usize| x as f64 / fs;
+        match format {
+            Format::Text => {
+                for (i, x) in regions.iter().enumerate() {
+                    writeln!(
+                        output,
+                        "{:.6}\t{:.6}\t{}",
+                        seconds(x.start),
+                        seconds(x.end),
+                        i + 1
+                    )?;
+                }
+            }
+            Format::Csv => {
+                writeln!(output, "start,end")?;
+                for x in regions {
+                    writeln!(
+                        output,
+                        "{},{}",
+                        seconds(x.start),
+                        seconds(x.end)
+                    )?;
+                }
+            }
+            Format::Json => {
+                let regions: Vec<serde_json::Value> = regions
+                    .iter()
+                    .map(|x| {
+                        serde_json::json!({
+                            "start": seconds(x.start),
+                            "end": seconds(x.end),
+                        })
+                    })
+                    .collect();
+                serde_json::to_writer_pretty(&mut *output, &regions)?;
+                writeln!(output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mono 1 kHz test input of the given duration in samples, which is
+/// digital silence except for the audio regions.
+#[cfg(test)]
+pub fn test_input(
+    audio: &[std::ops::Range<usize>],
+    duration: usize,
+) -> WavReader<std::io::Cursor<Vec<u8>>> {
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 1000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut wav = std::io::Cursor::new(Vec::new());
+    let mut writer = WavWriter::new(&mut wav, spec).unwrap();
+    for i in 0..duration {
+        let audio = audio.iter().any(|x| x.contains(&i));
+        writer
+            .write_sample(if audio { 0.5_f32 } else { 0.0 })
+            .unwrap();
+    }
+    writer.finalize().unwrap();
+    wav.set_position(0);
+    WavReader::new(wav).unwrap()
+}
+
+#[test]
+fn test_silence_regions() {
+    // Audio from 100 to 300, a short pause until 320 and audio until 900.
+    let mut input = test_input(&[100..300, 320..900], 1000);
+    let settings = Settings::new(-60.0, "50ms".parse().unwrap());
+    let regions = settings.analyze(&mut input, &[ChannelMap::Left]).unwrap();
+    assert_eq!(
+        regions,
+        [
+            Region { start: 0, end: 100 },
+            Region {
+                start: 900,
+                end: 1000
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_silence_cues() {
+    let regions = [
+        Region { start: 0, end: 100 },
+        Region {
+            start: 900,
+            end: 1000,
+        },
+    ];
+    let write = |format| {
+        let mut output = Vec::new();
+        Settings::write(&regions, 1000.0, format, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(
+        write(Format::Text),
+        "0.000000\t0.100000\t1\n0.900000\t1.000000\t2\n"
+    );
+    assert_eq!(write(Format::Csv), "start,end\n0,0.1\n0.9,1\n");
+    let json: serde_json::Value =
+        serde_json::from_str(&write(Format::Json)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"start": 0.0, "end": 0.1},
+            {"start": 0.9, "end": 1.0},
+        ])
+    );
+}
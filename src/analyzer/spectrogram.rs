@@ -0,0 +1,496 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::spectrum::{Stft, Window};
+use crate::conversion::Conversion;
+use crate::error::Error;
+use crate::frame::FrameIterator;
+use crate::progress::Progress;
+use hound::WavReader;
+
+/// Colour maps from low to high power
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Colormap {
+    /// Black to white
+    Gray,
+    /// Perceptually uniform blue to yellow
+    Viridis,
+    /// Perceptually uniform black to yellow via red
+    Inferno,
+}
+
+impl Colormap {
+    /// Returns the colour of a value between zero and one.
+    fn color(&self, x: f64) -> [u8; 3] {
+        let stops: &[[f64; 3]] = match self {
+            Self::Gray => &[[0.0, 0.0, 0.0], [255.0, 255.0, 255.0]],
+            Self::Viridis => &[
+                [68.0, 1.0, 84.0],
+                [71.0, 44.0, 122.0],
+                [59.0, 81.0, 139.0],
+                [44.0, 113.0, 142.0],
+                [33.0, 144.0, 141.0],
+                [39.0, 173.0, 129.0],
+                [92.0, 200.0, 99.0],
+                [170.0, 220.0, 50.0],
+                [253.0, 231.0, 37.0],
+            ],
+            Self::Inferno => &[
+                [0.0, 0.0, 4.0],
+                [31.0, 12.0, 72.0],
+                [85.0, 15.0, 109.0],
+                [136.0, 34.0, 106.0],
+                [186.0, 54.0, 85.0],
+                [227.0, 89.0, 51.0],
+                [249.0, 140.0, 10.0],
+                [249.0, 201.0, 50.0],
+                [252.0, 255.0, 164.0],
+            ],
+        };
+
+        // Linear interpolation between the stops
+        let pos = x.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (pos.floor() as usize).min(stops.len() - 2);
+        let t = pos - i as f64;
+        let mut color = [0; 3];
+        for (c, (a, b)) in
+            color.iter_mut().zip(stops[i].iter().zip(stops[i + 1]))
+        {
+            *c = (a + t * (b - a)).round() as u8;
+        }
+        color
+    }
+}
+
+/// Frequency axis scale
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Scale {
+    Linear,
+    Log,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+#[command(allow_negative_numbers = true)]
+pub struct Settings {
+    /// FFT size in samples
+    #[arg(long, default_value_t = 2048)]
+    fft_size: usize,
+    /// Number of samples between two FFT segments
+    #[arg(long, default_value_t = 512)]
+    hop: usize,
+    /// FFT window function
+    #[arg(long, value_enum, default_value_t = Window::Hann)]
+    window: Window,
+    /// Power in dBFS which is mapped to the lowest colour
+    #[arg(long, default_value_t = -120.0)]
+    min_db: f64,
+    /// Power in dBFS which is mapped to the highest colour
+    #[arg(long, default_value_t = 0.0)]
+    max_db: f64,
+    /// Colour map
+    #[arg(long, value_enum, default_value_t = Colormap::Inferno)]
+    colormap: Colormap,
+    /// Frequency axis scale
+    #[arg(long, value_enum, default_value_t = Scale::Log)]
+    frequency_scale: Scale,
+    /// Lowest frequency of the logarithmic frequency axis in Hz
+    #[arg(long, default_value_t = 20.0)]
+    min_frequency: f64,
+    /// Maximum width of the plot in pixels. The FFT segments of one pixel
+    /// column are averaged.
+    #[arg(long, default_value_t = 1200)]
+    width: usize,
+    /// Height of the plot in pixels
+    #[arg(long, default_value_t = 600)]
+    height: usize,
+}
+
+/// Scale factor of the label font
+const FONT_SCALE: usize = 2;
+/// Width of a label character including spacing in pixels
+const CHAR_WIDTH: usize = 6 * FONT_SCALE;
+/// Height of a label character in pixels
+const CHAR_HEIGHT: usize = 7 * FONT_SCALE;
+/// Length of the axis ticks in pixels
+const TICK: usize = 4;
+/// Space left of the plot for the frequency labels
+const MARGIN_LEFT: usize = 4 * CHAR_WIDTH + 2 * TICK;
+/// Space above the plot for the axis title
+const MARGIN_TOP: usize = CHAR_HEIGHT + 2 * TICK;
+/// Space right of the plot for the last time label
+const MARGIN_RIGHT: usize = 3 * CHAR_WIDTH;
+/// Space below the plot for the time labels
+const MARGIN_BOTTOM: usize = CHAR_HEIGHT + 3 * TICK;
+
+/// 5x7 pixel glyphs of the label characters, one byte per row
+const GLYPHS: [(char, [u8; 7]); 16] = [
+    ('0', [14, 17, 19, 21, 25, 17, 14]),
+    ('1', [4, 12, 4, 4, 4, 4, 14]),
+    ('2', [14, 17, 1, 2, 4, 8, 31]),
+    ('3', [31, 2, 4, 2, 1, 17, 14]),
+    ('4', [2, 6, 10, 18, 31, 2, 2]),
+    ('5', [31, 16, 30, 1, 1, 17, 14]),
+    ('6', [6, 8, 16, 30, 17, 17, 14]),
+    ('7', [31, 1, 2, 4, 8, 8, 8]),
+    ('8', [14, 17, 17, 14, 17, 17, 14]),
+    ('9', [14, 17, 17, 15, 1, 2, 12]),
+    ('.', [0, 0, 0, 0, 0, 12, 12]),
+    (':', [0, 12, 12, 0, 12, 12, 0]),
+    ('k', [16, 16, 18, 20, 24, 20, 18]),
+    ('H', [17, 17, 17, 31, 17, 17, 17]),
+    ('z', [0, 0, 31, 2, 4, 8, 31]),
+    ('s', [0, 0, 15, 16, 14, 1, 30]),
+];
+
+/// RGB image with the plot and its axes
+struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    const BACKGROUND: [u8; 3] = [255, 255, 255];
+    const FOREGROUND: [u8; 3] = [0, 0, 0];
+
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: Self::BACKGROUND.repeat(width * height),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = 3 * (y * self.width + x);
+            self.data[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    /// Draws the text with its top left corner at x, y.
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = match GLYPHS.iter().find(|(x, _)| *x == c) {
+                Some((_, glyph)) => glyph,
+                None => continue,
+            };
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..5 {
+                    if bits & (16 >> col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..FONT_SCALE {
+                        for dx in 0..FONT_SCALE {
+                            self.set(
+                                x + i * CHAR_WIDTH + col * FONT_SCALE + dx,
+                                y + row * FONT_SCALE + dy,
+                                Self::FOREGROUND,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn write<W>(&self, output: W) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        let mut encoder =
+            png::Encoder::new(output, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(writer.finish()?)
+    }
+}
+
+/// Returns the tick distance from the candidates so that the range has
+/// at most the given number of ticks.
+fn tick_step(range: f64, ticks: f64, candidates: &[f64]) -> f64 {
+    let step = range / ticks;
+    let magnitude = 10.0_f64.powf(step.log10().floor());
+    candidates
+        .iter()
+        .map(|x| x * magnitude)
+        .find(|x| *x >= step)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Formats a frequency in Hz as label, e.g. 500 or 2k.
+fn frequency_label(f: f64) -> String {
+    if f >= 1000.0 {
+        format!("{}k", (f / 100.0).round() / 10.0)
+    } else {
+        format!("{}", f.round())
+    }
+}
+
+/// Formats a time in seconds as label, e.g. 1.5s or 2:30.
+fn time_label(t: f64, step: f64) -> String {
+    if step >= 60.0 {
+        let t = t.round() as usize;
+        format!("{}:{:02}", t / 60, t % 60)
+    } else {
+        format!("{}s", (t * 1000.0).round() / 1000.0)
+    }
+}
+
+impl Settings {
+    /// Renders the STFT power of the summed input channels to a PNG image.
+    /// The input is streamed, only the averaged pixel columns are kept in
+    /// memory.
+    pub fn render<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: W,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write,
+    {
+        let spec = input.spec();
+        let fs = spec.sample_rate as f64;
+        let duration = input.duration() as usize;
+        if self.fft_size < 16 {
+            return Err(Error::InvalidArgument(
+                "FFT size must be at least 16 samples.".into(),
+            ));
+        }
+        if self.hop == 0 {
+            return Err(Error::InvalidArgument(
+                "Hop must be greater than zero.".into(),
+            ));
+        }
+        if self.min_db >= self.max_db {
+            return Err(Error::InvalidArgument(
+                "Minimum power must be less than maximum power.".into(),
+            ));
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidArgument(
+                "Plot width and height must be greater than zero.".into(),
+            ));
+        }
+        if self.min_frequency <= 0.0 || self.min_frequency >= fs / 2.0 {
+            return Err(Error::InvalidArgument(
+                "Minimum frequency must be between zero and half the \
+                 sampling rate."
+                    .into(),
+            ));
+        }
+        if duration < self.fft_size {
+            return Err(Error::InvalidArgument(
+                "Input is shorter than the FFT size.".into(),
+            ));
+        }
+
+        // Average the power of all segments which fall into a column.
+        let segments = (duration - self.fft_size) / self.hop + 1;
+        let width = self.width.min(segments);
+        let mut stft: Vec<Stft> = (0..spec.channels)
+            .map(|_| Stft::new(self.fft_size, self.window, self.hop))
+            .collect();
+        let bins = stft[0].bins();
+        let mut columns = vec![vec![0.0; bins]; width];
+        let mut counts = vec![0; width];
+        let mut segment = 0;
+
+        let mut progress = Progress::new(duration, "Analyzing sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        while let Some(frame) = frames.next() {
+            progress.next();
+            let frame = frame?;
+            let column = (segment * width / segments).min(width - 1);
+            let mut completed = false;
+            for (x, stft) in frame.iter().zip(stft.iter_mut()) {
+                if let Some(power) = stft.process(*x as f64) {
+                    for (acc, p) in columns[column].iter_mut().zip(power) {
+                        *acc += p;
+                    }
+                    completed = true;
+                }
+            }
+            if completed {
+                counts[column] += 1;
+                segment += 1;
+            }
+        }
+
+        // Frequency range of each pixel row from bottom to top
+        let nyquist = fs / 2.0;
+        let height = self.height;
+        let frequency = |row: f64| match self.frequency_scale {
+            Scale::Linear => row / height as f64 * nyquist,
+            Scale::Log => {
+                self.min_frequency
+                    * (nyquist / self.min_frequency).powf(row / height as f64)
+            }
+        };
+        let bin_width = fs / self.fft_size as f64;
+        let rows: Vec<std::ops::Range<usize>> = (0..height)
+            .map(|row| {
+                let start = (frequency(row as f64) / bin_width).ceil() as usize;
+                let end = (frequency(row as f64 + 1.0) / bin_width).ceil();
+                let end = (end as usize).min(bins);
+                if start < end {
+                    start..end
+                } else {
+                    // Row is narrower than a bin, use the nearest bin.
+                    let center = frequency(row as f64 + 0.5) / bin_width;
+                    let bin = (center.round() as usize).min(bins - 1);
+                    bin..bin + 1
+                }
+            })
+            .collect();
+
+        let mut image = Image::new(
+            MARGIN_LEFT + width + MARGIN_RIGHT,
+            MARGIN_TOP + height + MARGIN_BOTTOM,
+        );
+        for (x, (column, count)) in columns.iter().zip(counts).enumerate() {
+            for (row, bins) in rows.iter().enumerate() {
+                let power = column[bins.clone()].iter().sum::<f64>()
+                    / (bins.len() * count.max(1)) as f64;
+                let db = 10.0 * power.log10();
+                let color = self
+                    .colormap
+                    .color((db - self.min_db) / (self.max_db - self.min_db));
+                image.set(
+                    MARGIN_LEFT + x,
+                    MARGIN_TOP + height - 1 - row,
+                    color,
+                );
+            }
+        }
+
+        self.draw_axes(&mut image, width, duration as f64 / fs, nyquist);
+        image.write(output)
+    }
+
+    /// Draws the frequency ticks and labels left of the plot and the time
+    /// ticks and labels below the plot.
+    fn draw_axes(
+        &self,
+        image: &mut Image,
+        width: usize,
+        length: f64,
+        nyquist: f64,
+    ) {
+        let height = self.height;
+        let ticks: Vec<f64> = match self.frequency_scale {
+            Scale::Linear => {
+                let step = tick_step(nyquist, 8.0, &[1.0, 2.0, 5.0, 10.0]);
+                (0..)
+                    .map(|x| x as f64 * step)
+                    .take_while(|x| *x <= nyquist)
+                    .collect()
+            }
+            Scale::Log => (0..6)
+                .flat_map(|x| [1.0, 2.0, 5.0].map(|y| y * 10.0_f64.powi(x)))
+                .filter(|x| *x >= self.min_frequency && *x <= nyquist)
+                .collect(),
+        };
+        for f in ticks {
+            let pos = match self.frequency_scale {
+                Scale::Linear => f / nyquist,
+                Scale::Log => {
+                    (f / self.min_frequency).ln()
+                        / (nyquist / self.min_frequency).ln()
+                }
+            };
+            let y = MARGIN_TOP + ((1.0 - pos) * (height - 1) as f64) as usize;
+            for x in MARGIN_LEFT - TICK..MARGIN_LEFT {
+                image.set(x, y, Image::FOREGROUND);
+            }
+            let label = frequency_label(f);
+            let x = MARGIN_LEFT - 2 * TICK - label.len() * CHAR_WIDTH;
+            image.text(x, y.saturating_sub(CHAR_HEIGHT / 2), &label);
+        }
+        image.text(MARGIN_LEFT - 2 * TICK - 2 * CHAR_WIDTH, TICK, "Hz");
+
+        let step = tick_step(
+            length,
+            (width / (8 * CHAR_WIDTH)).max(1) as f64,
+            &[1.0, 2.0, 5.0, 10.0],
+        );
+        // Whole minutes for long inputs
+        let step = if step >= 60.0 {
+            60.0 * tick_step(step / 60.0, 1.0, &[1.0, 2.0, 5.0, 10.0])
+        } else {
+            step
+        };
+        let y = MARGIN_TOP + height;
+        for t in (0..).map(|x| x as f64 * step).take_while(|x| *x <= length) {
+            let x = MARGIN_LEFT + (t / length * (width - 1) as f64) as usize;
+            for dy in 0..TICK {
+                image.set(x, y + dy, Image::FOREGROUND);
+            }
+            let label = time_label(t, step);
+            let x = x.saturating_sub(label.len() * CHAR_WIDTH / 2);
+            image.text(x, y + 2 * TICK, &label);
+        }
+    }
+}
+
+#[test]
+fn test_spectrogram() {
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let mut wav = Generator::new(&signal, fs, 2, -20.0, 48000)
+        .unwrap()
+        .into_wav();
+    let settings = Settings {
+        fft_size: 256,
+        hop: 128,
+        window: Window::Hann,
+        min_db: -120.0,
+        max_db: 0.0,
+        colormap: Colormap::Gray,
+        frequency_scale: Scale::Linear,
+        min_frequency: 20.0,
+        width: 100,
+        height: 64,
+    };
+    let mut png = std::io::Cursor::new(Vec::new());
+    settings.render(&mut wav, &mut png).unwrap();
+
+    png.set_position(0);
+    let mut reader = png::Decoder::new(png).read_info().unwrap();
+    let (width, height) = reader.info().size();
+    assert_eq!(width as usize, MARGIN_LEFT + 100 + MARGIN_RIGHT);
+    assert_eq!(height as usize, MARGIN_TOP + 64 + MARGIN_BOTTOM);
+    let mut data = vec![0; (width * height * 3) as usize];
+    reader.next_frame(&mut data).unwrap();
+
+    // Rows span 375 Hz, the 1 kHz sine is in the third row from the
+    // bottom and the top row is far below -60 dBFS.
+    let gray = |x: usize, row: usize| {
+        let y = MARGIN_TOP + 64 - 1 - row;
+        data[3 * (y * width as usize + MARGIN_LEFT + x)]
+    };
+    assert!(gray(50, 2) > 180);
+    assert!(gray(50, 63) < 128);
+}
@@ -0,0 +1,489 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::conversion::Conversion;
+use crate::error::Error;
+use crate::frame::FrameIterator;
+use crate::progress::Progress;
+use crate::report::Format;
+use hound::WavReader;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// FFT window functions
+#[derive(Debug, Clone, Copy, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Window {
+    /// No window, best frequency resolution but high leakage
+    Rectangular,
+    /// Hann window
+    Hann,
+    /// Hamming window
+    Hamming,
+    /// Blackman window
+    Blackman,
+    /// 4-term Blackman-Harris window with very low leakage
+    BlackmanHarris,
+    /// Flat top window for accurate levels of sine tones
+    FlatTop,
+}
+
+impl Window {
+    /// Coefficients of the cosine-sum window.
+    fn cosine_terms(&self) -> &'static [f64] {
+        match self {
+            Self::Rectangular => &[1.0],
+            Self::Hann => &[0.5, 0.5],
+            Self::Hamming => &[0.54, 0.46],
+            Self::Blackman => &[0.42, 0.5, 0.08],
+            Self::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Self::FlatTop => &[
+                0.21557895,
+                0.41663158,
+                0.277263158,
+                0.083578947,
+                0.006947368,
+            ],
+        }
+    }
+
+    /// Periodic window of length "n" for spectral analysis.
+    fn coefficients(&self, n: usize) -> Vec<f64> {
+        let terms = self.cosine_terms();
+        (0..n)
+            .map(|i| {
+                let t = 2.0 * std::f64::consts::PI * i as f64 / n as f64;
+                terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * t).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Fractional octave bands
+#[derive(Debug, Clone, Copy, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Bands {
+    /// Octave bands from 16 Hz to 16 kHz
+    Octave,
+    /// Third-octave bands from 16 Hz to 20 kHz
+    ThirdOctave,
+}
+
+impl Bands {
+    /// Returns the base-2 center frequencies and band edges in Hz of the
+    /// bands whose center is below the Nyquist frequency. The upper edge
+    /// is limited to the Nyquist frequency.
+    fn bands(&self, fs: f64) -> Vec<(f64, f64, f64)> {
+        let (fraction, range) = match self {
+            Self::Octave => (1.0, -6..=4),
+            Self::ThirdOctave => (3.0, -18..=13),
+        };
+        range
+            .map(|x| {
+                let center = 1000.0 * 2.0_f64.powf(x as f64 / fraction);
+                let edge = 2.0_f64.powf(0.5 / fraction);
+                (center, center / edge, (center * edge).min(fs / 2.0))
+            })
+            .filter(|(center, _, _)| *center < fs / 2.0)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// FFT size in samples
+    #[arg(long, default_value_t = 4096)]
+    fft_size: usize,
+    /// Overlap of consecutive FFT segments in percent
+    #[arg(long, default_value_t = 50.0)]
+    overlap: f64,
+    /// FFT window function
+    #[arg(long, value_enum, default_value_t = Window::Hann)]
+    window: Window,
+    /// Sum the FFT bins into octave or third-octave bands
+    #[arg(long, value_enum)]
+    bands: Option<Bands>,
+    /// Analyze multiple channels independently instead of summing the
+    /// power of all channels
+    #[arg(short)]
+    channel_independent: bool,
+}
+
+/// Averaged power spectrum, one series per channel.
+#[derive(Debug, serde::Serialize)]
+pub struct Spectrum {
+    unit: &'static str,
+    sample_rate: u32,
+    fft_size: usize,
+    overlap: f64,
+    window: Window,
+    bands: Option<Bands>,
+    /// Number of averaged FFT segments
+    segments: usize,
+    /// Column names, one for each analyzed channel.
+    channels: Vec<String>,
+    /// Bin frequency or band center frequency in Hz.
+    frequency: Vec<f64>,
+    /// Power of each bin or band in dBFS, one vector per channel.
+    /// Bins without power read [`Spectrum::FLOOR_DB`].
+    values: Vec<Vec<f64>>,
+}
+
+impl Spectrum {
+    /// Lowest reported power in dBFS, e.g. of digital silence, which
+    /// would be -inf otherwise and has no JSON representation.
+    pub const FLOOR_DB: f64 = -200.0;
+}
+
+/// Short-time Fourier transform of one channel which returns the power
+/// spectrum of every completed segment.
+pub struct Stft {
+    fft: Arc<dyn Fft<f64>>,
+    /// Window coefficients
+    window: Vec<f64>,
+    /// Power normalization of the window
+    norm: f64,
+    /// Number of samples between two segments
+    hop: usize,
+    /// Latest input samples
+    buffer: VecDeque<f64>,
+    /// Number of processed samples
+    counter: usize,
+}
+
+impl Stft {
+    pub fn new(fft_size: usize, window: Window, hop: usize) -> Self {
+        let window = window.coefficients(fft_size);
+        // Scale to mean-square power so that the bins sum up to the
+        // power of the signal independent of the window.
+        let norm = fft_size as f64 * window.iter().map(|w| w * w).sum::<f64>();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(fft_size),
+            window,
+            norm,
+            hop: hop.max(1),
+            buffer: VecDeque::with_capacity(fft_size),
+            counter: 0,
+        }
+    }
+
+    /// Number of one-sided frequency bins
+    pub fn bins(&self) -> usize {
+        self.window.len() / 2 + 1
+    }
+
+    /// Adds a sample and returns the one-sided power spectrum if a
+    /// segment was completed.
+    pub fn process(&mut self, x: f64) -> Option<Vec<f64>> {
+        let n = self.window.len();
+        if self.buffer.len() == n {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(x);
+        self.counter += 1;
+        if self.counter < n || (self.counter - n) % self.hop != 0 {
+            return None;
+        }
+
+        let mut data: Vec<Complex<f64>> = self
+            .buffer
+            .iter()
+            .zip(self.window.iter())
+            .map(|(x, w)| Complex::new(x * w, 0.0))
+            .collect();
+        self.fft.process(&mut data);
+
+        Some(
+            data.iter()
+                .take(self.bins())
+                .enumerate()
+                .map(|(i, x)| {
+                    let one_sided =
+                        if i == 0 || 2 * i == n { 1.0 } else { 2.0 };
+                    one_sided * x.norm_sqr() / self.norm
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Welch power spectrum estimator of one channel.
+struct Welch {
+    stft: Stft,
+    /// Summed power spectra of all segments
+    power: Vec<f64>,
+    /// Number of summed segments
+    segments: usize,
+}
+
+impl Welch {
+    fn new(stft: Stft) -> Self {
+        Self {
+            power: vec![0.0; stft.bins()],
+            stft,
+            segments: 0,
+        }
+    }
+
+    fn process(&mut self, x: f64) {
+        if let Some(power) = self.stft.process(x) {
+            for (acc, p) in self.power.iter_mut().zip(power) {
+                *acc += p;
+            }
+            self.segments += 1;
+        }
+    }
+
+    /// Returns the averaged power of every bin.
+    fn power(&self) -> Vec<f64> {
+        self.power
+            .iter()
+            .map(|x| x / self.segments as f64)
+            .collect()
+    }
+}
+
+impl Settings {
+    /// Estimates the power spectrum of the input with Welch's method.
+    pub fn analyze<R>(
+        &self,
+        input: &mut WavReader<R>,
+    ) -> Result<Spectrum, Error>
+    where
+        R: std::io::Read,
+    {
+        if self.fft_size < 16 {
+            return Err(Error::InvalidArgument(
+                "FFT size must be at least 16 samples.".into(),
+            ));
+        }
+        if !(0.0..100.0).contains(&self.overlap) {
+            return Err(Error::InvalidArgument(
+                "Overlap must be at least 0 and less than 100 percent.".into(),
+            ));
+        }
+
+        let spec = input.spec();
+        let duration = input.duration();
+        if (duration as usize) < self.fft_size {
+            return Err(Error::InvalidArgument(
+                "Input is shorter than the FFT size.".into(),
+            ));
+        }
+
+        let fs = spec.sample_rate as f64;
+        let hop =
+            ((self.fft_size as f64 * (1.0 - self.overlap / 100.0)).round()
+                as usize)
+                .max(1);
+        let mut welch: Vec<Welch> = (0..spec.channels)
+            .map(|_| Welch::new(Stft::new(self.fft_size, self.window, hop)))
+            .collect();
+
+        let mut progress = Progress::new(duration as usize, "Analyzing sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        while let Some(frame) = frames.next() {
+            progress.next();
+            match frame {
+                Ok(frame) => {
+                    for (x, w) in frame.iter().zip(welch.iter_mut()) {
+                        w.process(*x as f64);
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut power: Vec<Vec<f64>> =
+            welch.iter().map(|x| x.power()).collect();
+        let mut channels: Vec<String> =
+            (1..=power.len()).map(|x| format!("ch{}", x)).collect();
+        if !self.channel_independent {
+            let sum = (0..power[0].len())
+                .map(|i| power.iter().map(|x| x[i]).sum())
+                .collect();
+            power = vec![sum];
+            channels = vec!["mix".into()];
+        }
+
+        let bin_width = fs / self.fft_size as f64;
+        let mut frequency: Vec<f64> =
+            (0..power[0].len()).map(|i| i as f64 * bin_width).collect();
+        if let Some(bands) = self.bands {
+            // Bands without any FFT bin are omitted.
+            let bands: Vec<(f64, std::ops::Range<usize>)> = bands
+                .bands(fs)
+                .iter()
+                .map(|(center, lower, upper)| {
+                    let start = (lower / bin_width).ceil() as usize;
+                    // The Nyquist bin belongs to the highest band.
+                    let end = if *upper < fs / 2.0 {
+                        (upper / bin_width).ceil() as usize
+                    } else {
+                        frequency.len()
+                    };
+                    (*center, start..end.min(frequency.len()))
+                })
+                .filter(|(_, bins)| !bins.is_empty())
+                .collect();
+            frequency = bands.iter().map(|(center, _)| *center).collect();
+            power = power
+                .iter()
+                .map(|x| {
+                    bands
+                        .iter()
+                        .map(|(_, bins)| x[bins.clone()].iter().sum())
+                        .collect()
+                })
+                .collect();
+        }
+
+        Ok(Spectrum {
+            unit: "dBFS",
+            sample_rate: spec.sample_rate,
+            fft_size: self.fft_size,
+            overlap: self.overlap,
+            window: self.window,
+            bands: self.bands,
+            segments: welch[0].segments,
+            channels,
+            frequency,
+            values: power
+                .iter()
+                .map(|x| {
+                    x.iter()
+                        .map(|x| (10.0 * x.log10()).max(Spectrum::FLOOR_DB))
+                        .collect()
+                })
+                .collect(),
+        })
+    }
+
+    /// Writes the spectrum in the selected format.
+    /// Text output is identical to CSV output.
+    pub fn write<W>(
+        &self,
+        spectrum: &Spectrum,
+        format: Format,
+        output: &mut W,
+    ) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        match format {
+            Format::Text | Format::Csv => {
+                write!(output, "frequency")?;
+                for channel in &spectrum.channels {
+                    write!(output, ",{}", channel)?;
+                }
+                writeln!(output)?;
+                for (i, frequency) in spectrum.frequency.iter().enumerate() {
+                    write!(output, "{}", frequency)?;
+                    for values in &spectrum.values {
+                        write!(output, ",{}", values[i])?;
+                    }
+                    writeln!(output)?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *output, spectrum)?;
+                writeln!(output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_spectrum() {
+    use crate::generator::{Generator, Signal};
+
+    // Stereo 1 kHz sine with -20 dBFS peak, -23 dBFS power per channel
+    let fs = 48000.0;
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let wav = || {
+        Generator::new(&signal, fs, 2, -20.0, 48000)
+            .unwrap()
+            .into_wav()
+    };
+    let mut settings = Settings {
+        fft_size: 4096,
+        overlap: 50.0,
+        window: Window::Hann,
+        bands: None,
+        channel_independent: true,
+    };
+
+    // The bins sum up to the signal power, the peak is at 1 kHz.
+    let spectrum = settings.analyze(&mut wav()).unwrap();
+    assert_eq!(spectrum.segments, 22);
+    for values in &spectrum.values {
+        let power: f64 = values.iter().map(|x| 10.0_f64.powf(x / 10.0)).sum();
+        assert!((10.0 * power.log10() + 23.01).abs() < 0.01);
+        let peak = (0..values.len())
+            .max_by(|a, b| values[*a].total_cmp(&values[*b]))
+            .unwrap();
+        assert!((spectrum.frequency[peak] - 1000.0).abs() < fs / 4096.0);
+    }
+
+    // The 1 kHz third-octave band of the summed channels has 3 dB more
+    // power, the neighbouring bands are far below.
+    settings.bands = Some(Bands::ThirdOctave);
+    settings.channel_independent = false;
+    let spectrum = settings.analyze(&mut wav()).unwrap();
+    let band = spectrum
+        .frequency
+        .iter()
+        .position(|x| (x - 1000.0).abs() < 1.0)
+        .unwrap();
+    assert!((spectrum.values[0][band] + 20.0).abs() < 0.01);
+    assert!(spectrum.values[0][band - 1] < -80.0);
+    assert!(spectrum.values[0][band + 1] < -80.0);
+
+    // The upper edges of the 16 kHz octave and the 20 kHz third-octave band
+    // are above the Nyquist frequency of 22.05 kHz, both bands are kept.
+    let wav = || {
+        Generator::new(&Signal::Silence, 44100.0, 1, 0.0, 8192)
+            .unwrap()
+            .into_wav()
+    };
+    settings.bands = Some(Bands::Octave);
+    let spectrum = settings.analyze(&mut wav()).unwrap();
+    assert_eq!(spectrum.frequency.last(), Some(&16000.0));
+    settings.bands = Some(Bands::ThirdOctave);
+    let spectrum = settings.analyze(&mut wav()).unwrap();
+    let last = spectrum.frequency.last().unwrap();
+    assert!((last - 1000.0 * 2.0_f64.powf(13.0 / 3.0)).abs() < 1e-9);
+
+    // Digital silence reads the floor instead of -inf.
+    assert!(spectrum.values[0].iter().all(|x| *x == Spectrum::FLOOR_DB));
+    let mut json = Vec::new();
+    settings.write(&spectrum, Format::Json, &mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["values"][0][0], -200.0);
+}
@@ -0,0 +1,359 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{loudness::Loudness, rms::Rms, true_peak::TruePeak};
+use crate::conversion::Conversion;
+use crate::error::Error;
+use crate::frame::{ChannelMap, FrameIterator};
+use crate::progress::Progress;
+use crate::report::Format;
+use hound::WavReader;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quantity {
+    /// Momentary (400 ms) EBU R128 loudness in LUFS
+    Momentary,
+    /// Short-term (3 s) EBU R128 loudness in LUFS
+    ShortTerm,
+    /// RMS of each window in dBFS
+    Rms,
+    /// True peak of each window in dBTP
+    TruePeak,
+}
+
+impl Quantity {
+    fn unit(&self) -> &'static str {
+        match self {
+            Self::Momentary | Self::ShortTerm => "LUFS",
+            Self::Rms => "dBFS",
+            Self::TruePeak => "dBTP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// Quantity to analyze
+    quantity: Quantity,
+    /// Time between two values in seconds
+    #[arg(long, default_value_t = 0.1)]
+    hop: f64,
+    /// Analyze multiple channels independently
+    #[arg(short)]
+    channel_independent: bool,
+    /// Do not normalize loudness to stereo.
+    /// You should only use this flag if you have to be strictly
+    /// EBU R128 compliant.
+    #[arg(short)]
+    strict_ebur128: bool,
+}
+
+/// Analyzed values over time, one series per channel.
+#[derive(Debug, serde::Serialize)]
+pub struct TimeSeries {
+    quantity: Quantity,
+    unit: &'static str,
+    sample_rate: u32,
+    hop: f64,
+    /// Column names, one for each analyzed channel.
+    channels: Vec<String>,
+    /// End of each analysis window in seconds.
+    time: Vec<f64>,
+    /// Analyzed values in dB units, one vector per channel.
+    values: Vec<Vec<f64>>,
+}
+
+/// Per-frame analyzer which is polled at the end of each window.
+enum Analyzer {
+    Loudness(Loudness),
+    Rms(Rms),
+    TruePeak(TruePeak),
+}
+
+impl Analyzer {
+    fn process(&mut self, frame: &Vec<f32>) -> Result<(), Error> {
+        match self {
+            Self::Loudness(x) => x.process(frame),
+            Self::Rms(x) => x.process(frame),
+            Self::TruePeak(x) => x.process(frame),
+        }
+    }
+
+    /// Returns the value of the current window in dB units and
+    /// starts a new window. Loudness is None until the first block is
+    /// complete.
+    fn poll(&mut self, quantity: Quantity, loudness_norm: f64) -> Option<f64> {
+        match self {
+            Self::Loudness(x) => {
+                let loudness = match quantity {
+                    Quantity::ShortTerm => x.short_term_loudness(),
+                    _ => x.momentary_loudness(),
+                };
+                loudness.map(|x| 10.0 * (loudness_norm * x).log10())
+            }
+            Self::Rms(x) => {
+                let rms = x.rms();
+                x.reset();
+                Some(20.0 * rms.log10())
+            }
+            Self::TruePeak(x) => {
+                let true_peak = x.true_peak();
+                x.reset();
+                Some(20.0 * true_peak.log10())
+            }
+        }
+    }
+}
+
+impl Settings {
+    /// Analyzes the input, the layout gives the speaker position of
+    /// every input channel.
+    pub fn analyze<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+    ) -> Result<TimeSeries, Error>
+    where
+        R: std::io::Read,
+    {
+        if self.hop <= 0.0 {
+            return Err(Error::InvalidArgument(
+                "Hop must be greater than zero.".into(),
+            ));
+        }
+        if layout.len() != input.spec().channels as usize {
+            return Err(Error::InvalidArgument(
+                "Channel layout does not match the number of channels.".into(),
+            ));
+        }
+
+        let spec = input.spec();
+        let duration = input.duration();
+        let fs = spec.sample_rate as f64;
+        let loudness =
+            matches!(self.quantity, Quantity::Momentary | Quantity::ShortTerm);
+        let weights = ChannelMap::weights(layout);
+        // Independent loudness keeps the BS.1770 weight of each channel,
+        // the LFE is skipped in both modes.
+        let selected: Vec<Option<usize>> = if self.channel_independent {
+            (0..spec.channels as usize)
+                .filter(|i| !loudness || weights[*i] > 0.0)
+                .map(Some)
+                .collect()
+        } else {
+            vec![None]
+        };
+        let mut analyzer = selected
+            .iter()
+            .map(|channel| match (self.quantity, channel) {
+                (Quantity::Momentary | Quantity::ShortTerm, Some(i)) => {
+                    Analyzer::Loudness(Loudness::with_weights(
+                        fs,
+                        vec![weights[*i]],
+                    ))
+                }
+                (Quantity::Momentary | Quantity::ShortTerm, None) => {
+                    Analyzer::Loudness(Loudness::with_layout(fs, layout))
+                }
+                (Quantity::Rms, Some(_)) => Analyzer::Rms(Rms::new(1)),
+                (Quantity::Rms, None) => {
+                    Analyzer::Rms(Rms::new(spec.channels as usize))
+                }
+                (Quantity::TruePeak, Some(_)) => {
+                    Analyzer::TruePeak(TruePeak::new(1))
+                }
+                (Quantity::TruePeak, None) => {
+                    Analyzer::TruePeak(TruePeak::new(spec.channels as usize))
+                }
+            })
+            .collect::<Vec<Analyzer>>();
+        let loudness_norm = if self.strict_ebur128 || self.channel_independent {
+            1.0
+        } else {
+            2.0 / spec.channels as f64
+        };
+
+        let mut series = TimeSeries {
+            quantity: self.quantity,
+            unit: self.quantity.unit(),
+            sample_rate: spec.sample_rate,
+            hop: self.hop,
+            channels: selected
+                .iter()
+                .map(|channel| match channel {
+                    Some(i) => format!("ch{}", i + 1),
+                    None => "mix".into(),
+                })
+                .collect(),
+            time: Vec::new(),
+            values: vec![Vec::new(); selected.len()],
+        };
+
+        let hop_len = ((self.hop * fs).round() as usize).max(1);
+        let mut counter = 0;
+        let mut progress = Progress::new(duration as usize, "Analyzing sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        while let Some(frame) = frames.next() {
+            progress.next();
+            match frame {
+                Ok(frame) => {
+                    for (a, channel) in analyzer.iter_mut().zip(&selected) {
+                        match channel {
+                            Some(i) => a.process(&vec![frame[*i]])?,
+                            None => a.process(frame)?,
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            counter += 1;
+            if counter % hop_len == 0 {
+                self.poll(&mut series, &mut analyzer, counter, loudness_norm);
+            }
+        }
+
+        // Trailing partial window
+        if counter % hop_len != 0 {
+            self.poll(&mut series, &mut analyzer, counter, loudness_norm);
+        }
+
+        Ok(series)
+    }
+
+    /// Appends the values of all analyzers at the given sample count.
+    /// Rows without a complete loudness block are skipped.
+    fn poll(
+        &self,
+        series: &mut TimeSeries,
+        analyzer: &mut [Analyzer],
+        counter: usize,
+        loudness_norm: f64,
+    ) {
+        let values: Option<Vec<f64>> = analyzer
+            .iter_mut()
+            .map(|x| x.poll(self.quantity, loudness_norm))
+            .collect();
+        if let Some(values) = values {
+            series.time.push(counter as f64 / series.sample_rate as f64);
+            for (x, column) in values.into_iter().zip(series.values.iter_mut())
+            {
+                column.push(x);
+            }
+        }
+    }
+
+    /// Writes the time series in the selected format.
+    /// Text output is identical to CSV output.
+    pub fn write<W>(
+        &self,
+        series: &TimeSeries,
+        format: Format,
+        output: &mut W,
+    ) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        match format {
+            Format::Text | Format::Csv => {
+                write!(output, "time")?;
+                for channel in &series.channels {
+                    write!(output, ",{}", channel)?;
+                }
+                writeln!(output)?;
+                for (i, time) in series.time.iter().enumerate() {
+                    write!(output, "{}", time)?;
+                    for values in &series.values {
+                        write!(output, ",{}", values[i])?;
+                    }
+                    writeln!(output)?;
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *output, series)?;
+                writeln!(output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_time_series() {
+    use crate::generator::{Generator, Signal};
+
+    // 1.05 s stereo 1 kHz sine with -20 dBFS peak, -23 dBFS RMS and
+    // -20 LUFS
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let wav = || {
+        Generator::new(&signal, 48000.0, 2, -20.0, 50400)
+            .unwrap()
+            .into_wav()
+    };
+    let layout = ChannelMap::default_layout(2);
+    let settings_for = |quantity| Settings {
+        quantity,
+        hop: 0.1,
+        channel_independent: false,
+        strict_ebur128: false,
+    };
+
+    // Ten complete windows and the trailing 50 ms
+    let series = settings_for(Quantity::Rms)
+        .analyze(&mut wav(), &layout)
+        .unwrap();
+    assert_eq!(series.time.len(), 11);
+    assert!((series.time[10] - 1.05).abs() < 1e-9);
+    assert!(series.values[0].iter().all(|x| (x + 23.01).abs() < 0.01));
+
+    // Loudness rows start with the first complete 400 ms block.
+    let settings = settings_for(Quantity::Momentary);
+    let series = settings.analyze(&mut wav(), &layout).unwrap();
+    assert_eq!(series.time.len(), 8);
+    assert!((series.time[0] - 0.4).abs() < 1e-9);
+    assert!(series.values[0].iter().all(|x| (x + 20.0).abs() < 0.1));
+
+    let mut csv = Vec::new();
+    settings.write(&series, Format::Csv, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().next(), Some("time,mix"));
+    assert_eq!(csv.lines().count(), series.time.len() + 1);
+
+    // Independent 5.1 loudness skips the LFE, RMS keeps all channels.
+    let wav = || {
+        Generator::new(&signal, 48000.0, 6, -20.0, 50400)
+            .unwrap()
+            .into_wav()
+    };
+    let layout = ChannelMap::default_layout(6);
+    let independent = |quantity| Settings {
+        channel_independent: true,
+        ..settings_for(quantity)
+    };
+    let series = independent(Quantity::Momentary)
+        .analyze(&mut wav(), &layout)
+        .unwrap();
+    assert_eq!(series.channels, ["ch1", "ch2", "ch3", "ch5", "ch6"]);
+    let series = independent(Quantity::Rms)
+        .analyze(&mut wav(), &layout)
+        .unwrap();
+    assert_eq!(series.channels.len(), 6);
+}
@@ -116,8 +116,67 @@ impl TruePeak {
         Ok(())
     }
 
+    /// Reset the detected true peak, e.g. to start a new analysis window.
+    /// The upsampling filter state is kept.
+    pub fn reset(&mut self) {
+        self.true_peak = 0.0;
+    }
+
     /// Returns the detected true peak.
     pub fn true_peak(&self) -> f64 {
         self.true_peak
     }
 }
+
+#[test]
+fn test_true_peak_ebu3341() {
+    use crate::effects::{envelope::Gain, Effect};
+    use crate::envelope::{Breakpoint, Curve, Envelope};
+    use crate::generator::{Generator, Signal};
+
+    // EBU Tech 3341 cases 15 to 19 with the frequencies scaled to the
+    // sample rate: sines whose peaks fall between the samples, expected
+    // true peak within +0.2/-0.4 dB.
+    // (frequency divisor, phase in degrees, amplitude in dBFS)
+    let cases = [
+        (4.0, 0.0, -6.0),
+        (4.0, 45.0, -6.0),
+        (6.0, 60.0, -6.0),
+        (8.0, 67.5, -6.0),
+        (4.0, 45.0, 3.0),
+    ];
+    for fs in [44100.0, 48000.0, 96000.0] {
+        for (divisor, phase, level) in cases {
+            let signal = Signal::Sine {
+                frequency: fs / divisor,
+                phase,
+            };
+            let generator =
+                Generator::new(&signal, fs, 2, level, fs as usize).unwrap();
+            // An abrupt onset would ring, the sine fades in over 100 ms.
+            let point = |position, value| Breakpoint {
+                position,
+                value,
+                curve: Curve::Cosine,
+            };
+            let fade_in = vec![point(0, 0.0), point(fs as usize / 10, 1.0)];
+            let mut fade_in =
+                Gain::new(Envelope::new(fade_in).unwrap(), false, 2);
+            let mut true_peak = TruePeak::new(2);
+            for frame in generator {
+                true_peak
+                    .process(&fade_in.process(&frame).unwrap())
+                    .unwrap();
+            }
+            let db = 20.0 * true_peak.true_peak().log10();
+            assert!(
+                db - level < 0.2 && db - level > -0.4,
+                "{} Hz, case {} Hz {} deg: {} dB",
+                fs,
+                fs / divisor,
+                phase,
+                db
+            );
+        }
+    }
+}
@@ -0,0 +1,733 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+//! Audio file formats. All operations process `hound` WAV streams,
+//! FLAC files are transcoded from and to WAV block by block.
+use crate::error::Error;
+use flacenc::component::{BitRepr, Stream, StreamInfo};
+use flacenc::error::{Verified, Verify};
+use flacenc::source::{Context, Fill, FrameBuf};
+use hound::{Sample, SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+
+/// Maximum bit depth of the FLAC encoder
+const FLAC_MAX_BITS: u16 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// RIFF WAVE
+    Wav,
+    /// Free Lossless Audio Codec
+    Flac,
+}
+
+impl Format {
+    /// Selects the format by file extension, defaults to WAV.
+    pub fn from_filename(filename: &str) -> Self {
+        match std::path::Path::new(filename)
+            .extension()
+            .and_then(|x| x.to_str())
+        {
+            Some(x) if x.eq_ignore_ascii_case("flac") => Self::Flac,
+            _ => Self::Wav,
+        }
+    }
+}
+
+/// Input stream of a WAV file or a decoded FLAC file.
+#[derive(Debug)]
+pub enum Input {
+    File(BufReader<File>),
+    Flac(Box<FlacDecoder<BufReader<File>>>),
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::File(x) => x.read(buf),
+            Self::Flac(x) => x.read(buf),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::File(x) => x.seek(pos),
+            Self::Flac(x) => x.seek(pos),
+        }
+    }
+}
+
+/// Opens a WAV or FLAC file, selected by file extension.
+pub fn open(filename: &str) -> Result<WavReader<Input>, Error> {
+    let file = BufReader::new(File::open(filename)?);
+    let input = match Format::from_filename(filename) {
+        Format::Wav => Input::File(file),
+        Format::Flac => Input::Flac(Box::new(FlacDecoder::new(file)?)),
+    };
+
+    Ok(WavReader::new(input)?)
+}
+
+/// Reads the WAVE_FORMAT_EXTENSIBLE channel mask of a WAV or FLAC file.
+/// Returns None if the file has no mask.
+pub fn channel_mask(filename: &str) -> Result<Option<u32>, Error> {
+    match Format::from_filename(filename) {
+        Format::Wav => wav_channel_mask(BufReader::new(File::open(filename)?)),
+        Format::Flac => {
+            let reader = claxon::FlacReader::open(filename)?;
+            if let Some(x) =
+                reader.get_tag("WAVEFORMATEXTENSIBLE_CHANNEL_MASK").next()
+            {
+                let mask = x.trim_start_matches("0x").trim_start_matches("0X");
+                return match u32::from_str_radix(mask, 16) {
+                    Ok(mask) => Ok(Some(mask)),
+                    Err(_) => Ok(None),
+                };
+            }
+            // Channel assignment of the FLAC format specification
+            Ok(match reader.streaminfo().channels {
+                1 => Some(0x4),
+                2 => Some(0x3),
+                3 => Some(0x7),
+                4 => Some(0x33),
+                5 => Some(0x37),
+                6 => Some(0x3f),
+                7 => Some(0x70f),
+                8 => Some(0x63f),
+                _ => None,
+            })
+        }
+    }
+}
+
+/// Searches the "fmt " chunk of a RIFF WAVE stream for the channel mask.
+fn wav_channel_mask<R>(mut input: R) -> Result<Option<u32>, Error>
+where
+    R: Read,
+{
+    let mut header = [0; 12];
+    input.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Ok(None);
+    }
+
+    loop {
+        let mut chunk = [0; 8];
+        input.read_exact(&mut chunk)?;
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        if &chunk[0..4] != b"fmt " {
+            // Chunks are padded to an even size.
+            let len = len as u64 + len as u64 % 2;
+            std::io::copy(&mut (&mut input).take(len), &mut std::io::sink())?;
+            continue;
+        }
+
+        let mut fmt = vec![0; len as usize];
+        input.read_exact(&mut fmt)?;
+        let format_tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+        // WAVE_FORMAT_EXTENSIBLE has a mask at byte 20 of the chunk.
+        return Ok(if format_tag == 0xfffe && fmt.len() >= 24 {
+            Some(u32::from_le_bytes([fmt[20], fmt[21], fmt[22], fmt[23]]))
+        } else {
+            None
+        });
+    }
+}
+
+/// Converts an error of the FLAC codecs into an IO error.
+fn io_error<E>(e: E) -> std::io::Error
+where
+    E: std::fmt::Display,
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// WAV stream with the bit depth and sample rate of a FLAC stream, which
+/// is decoded one block at a time. Seeking backwards restarts the decoder
+/// at the beginning of the FLAC stream.
+pub struct FlacDecoder<R>
+where
+    R: Read + Seek,
+{
+    /// FLAC decoder, only taken while restarting
+    reader: Option<claxon::FlacReader<R>>,
+    /// WAV header with the final chunk sizes
+    header: Vec<u8>,
+    /// Bits per sample
+    bits: u16,
+    /// WAV data of the current block
+    block: Vec<u8>,
+    /// Stream position of the current block
+    block_start: u64,
+    /// Current stream position
+    position: u64,
+    /// Total stream length in bytes
+    len: u64,
+    /// Sample buffer of the decoder
+    buffer: Vec<i32>,
+}
+
+impl<R> FlacDecoder<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(input: R) -> Result<Self, Error> {
+        let reader = claxon::FlacReader::new(input)?;
+        let info = reader.streaminfo();
+        let spec = WavSpec {
+            channels: info.channels as u16,
+            sample_rate: info.sample_rate,
+            bits_per_sample: info.bits_per_sample as u16,
+            sample_format: SampleFormat::Int,
+        };
+        let samples = info.samples.ok_or_else(|| {
+            Error::Flac(
+                "FLAC streams without sample count are not supported.".into(),
+            )
+        })?;
+        let data_len = samples
+            * info.channels as u64
+            * ((spec.bits_per_sample as u64 + 7) / 8);
+
+        // Header of an empty WAV file, patched with the final chunk sizes.
+        let mut header = Cursor::new(Vec::new());
+        WavWriter::new(&mut header, spec)?.finalize()?;
+        let mut header = header.into_inner();
+        let header_len = header.len();
+        let riff_len = (header_len - 8) as u64 + data_len;
+        if riff_len > u32::MAX as u64 {
+            return Err(Error::Flac(
+                "FLAC stream exceeds the 4 GiB size limit of WAV.".into(),
+            ));
+        }
+        header[4..8].copy_from_slice(&(riff_len as u32).to_le_bytes());
+        header[header_len - 4..]
+            .copy_from_slice(&(data_len as u32).to_le_bytes());
+
+        Ok(Self {
+            reader: Some(reader),
+            header,
+            bits: spec.bits_per_sample,
+            block: Vec::new(),
+            block_start: header_len as u64,
+            position: 0,
+            len: header_len as u64 + data_len,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Decodes the next block, returns false at the end of the stream.
+    fn next_block(&mut self) -> std::io::Result<bool> {
+        let reader = self
+            .reader
+            .as_mut()
+            .ok_or_else(|| io_error("FLAC decoder failed to restart."))?;
+        let buffer = std::mem::take(&mut self.buffer);
+        let block = match reader.blocks().read_next_or_eof(buffer) {
+            Ok(Some(x)) => x,
+            Ok(None) => return Ok(false),
+            Err(e) => return Err(io_error(e)),
+        };
+
+        self.block_start += self.block.len() as u64;
+        self.block.clear();
+        let bytes = (self.bits + 7) / 8;
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                block
+                    .sample(ch, i)
+                    .write_padded(&mut self.block, self.bits, bytes)
+                    .map_err(io_error)?;
+            }
+        }
+        self.buffer = block.into_buffer();
+
+        Ok(true)
+    }
+
+    /// Restarts decoding at the first block.
+    fn restart(&mut self) -> std::io::Result<()> {
+        if let Some(reader) = self.reader.take() {
+            let mut input = reader.into_inner();
+            input.seek(SeekFrom::Start(0))?;
+            self.reader =
+                Some(claxon::FlacReader::new(input).map_err(io_error)?);
+        }
+        self.block.clear();
+        self.block_start = self.header.len() as u64;
+
+        Ok(())
+    }
+}
+
+impl<R> Read for FlacDecoder<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = if self.position < self.block_start {
+            &self.header[self.position as usize..]
+        } else {
+            while self.position >= self.block_start + self.block.len() as u64 {
+                if !self.next_block()? {
+                    return Ok(0);
+                }
+            }
+            &self.block[(self.position - self.block_start) as usize..]
+        };
+
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R> Seek for FlacDecoder<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::Current(x) => self.position as i64 + x,
+            SeekFrom::End(x) => self.len as i64 + x,
+        };
+        if position < 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before the start of the stream.",
+            ));
+        }
+
+        self.position = position as u64;
+        let header_len = self.header.len() as u64;
+        if self.position < self.block_start && self.block_start > header_len {
+            self.restart()?;
+        }
+        Ok(self.position)
+    }
+}
+
+impl<R> std::fmt::Debug for FlacDecoder<R>
+where
+    R: Read + Seek,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FlacDecoder")
+            .field("bits", &self.bits)
+            .field("position", &self.position)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// FLAC encoder which consumes the stream of a [`WavWriter`] and writes
+/// every block as soon as it is complete. [`FlacEncoder::finish`] rewrites
+/// the stream info at the beginning of the output.
+pub struct FlacEncoder<W>
+where
+    W: Write + Seek,
+{
+    output: W,
+    config: Verified<flacenc::config::Encoder>,
+    stream_info: StreamInfo,
+    /// Frame buffer and MD5 context of the current block
+    frame: (FrameBuf, Context),
+    /// Interleaved samples of the current block
+    samples: Vec<i32>,
+    /// Number of samples per block and channel
+    block_size: usize,
+    /// Number of channels
+    channels: usize,
+    /// Bits per sample
+    bits: u16,
+    /// WAV header until the data chunk was found
+    header: Vec<u8>,
+    /// Stream position of the first sample, None until the header is complete
+    data_start: Option<u64>,
+    /// Bytes of an incomplete sample
+    partial: Vec<u8>,
+    /// Current stream position
+    position: u64,
+    /// Total stream length in bytes
+    len: u64,
+}
+
+impl<W> FlacEncoder<W>
+where
+    W: Write + Seek,
+{
+    pub fn new(output: W, spec: WavSpec) -> Result<Self, Error> {
+        if spec.sample_format == SampleFormat::Float
+            || !(8..=FLAC_MAX_BITS).contains(&spec.bits_per_sample)
+        {
+            return Err(Error::InvalidArgument(format!(
+                "FLAC requires integer samples with 8 to {} bits.",
+                FLAC_MAX_BITS
+            )));
+        }
+
+        let channels = spec.channels as usize;
+        let bits = spec.bits_per_sample as usize;
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, e)| Error::Flac(e.to_string()))?;
+        let block_size = config.block_size;
+        let mut stream_info =
+            StreamInfo::new(spec.sample_rate as usize, channels, bits)
+                .map_err(|e| Error::Flac(e.to_string()))?;
+        // Follows the reference encoder for streams with one short block.
+        stream_info
+            .set_block_sizes(block_size, block_size)
+            .map_err(|e| Error::Flac(e.to_string()))?;
+        let frame = (
+            FrameBuf::with_size(channels, block_size)
+                .map_err(|e| Error::Flac(e.to_string()))?,
+            Context::new(bits, channels),
+        );
+
+        let mut encoder = Self {
+            output,
+            config,
+            stream_info,
+            frame,
+            samples: Vec::with_capacity(block_size * channels),
+            block_size,
+            channels,
+            bits: spec.bits_per_sample,
+            header: Vec::new(),
+            data_start: None,
+            partial: Vec::new(),
+            position: 0,
+            len: 0,
+        };
+        encoder.write_stream_info()?;
+
+        Ok(encoder)
+    }
+
+    /// Writes the stream header with the current stream info.
+    fn write_stream_info(&mut self) -> Result<(), Error> {
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        Stream::with_stream_info(self.stream_info.clone())
+            .write(&mut sink)
+            .map_err(|e| Error::Flac(e.to_string()))?;
+        self.output.write_all(sink.as_slice())?;
+        Ok(())
+    }
+
+    /// Returns the stream position of the first sample if the header
+    /// contains the data chunk.
+    fn find_data(&self) -> Option<u64> {
+        let mut offset = 12;
+        while offset + 8 <= self.header.len() {
+            if &self.header[offset..offset + 4] == b"data" {
+                return Some(offset as u64 + 8);
+            }
+            let len = &self.header[offset + 4..offset + 8];
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]);
+            // Chunks are padded to an even size.
+            offset += 8 + len as usize + len as usize % 2;
+        }
+        None
+    }
+
+    /// Converts the WAV sample data to samples and encodes complete blocks.
+    fn push(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let bytes = (self.bits as usize + 7) / 8;
+        let mut partial = std::mem::take(&mut self.partial);
+        partial.extend_from_slice(data);
+
+        let mut samples = partial.chunks_exact(bytes);
+        for x in &mut samples {
+            let sample = if bytes == 1 {
+                x[0] as i32 - 128
+            } else {
+                // Sign extend the little endian integer.
+                let mut value = [0; 4];
+                value[4 - bytes..].copy_from_slice(x);
+                i32::from_le_bytes(value) >> (32 - 8 * bytes)
+            };
+            self.samples.push(sample);
+            if self.samples.len() == self.block_size * self.channels {
+                self.encode_block()?;
+            }
+        }
+        self.partial = samples.remainder().to_vec();
+
+        Ok(())
+    }
+
+    /// Encodes the buffered samples as one frame.
+    fn encode_block(&mut self) -> std::io::Result<()> {
+        self.frame
+            .fill_interleaved(&self.samples)
+            .map_err(io_error)?;
+        let frame = flacenc::encode_fixed_size_frame(
+            &self.config,
+            &self.frame.0,
+            self.frame.1.current_frame_number().unwrap_or(0),
+            &self.stream_info,
+        )
+        .map_err(io_error)?;
+        self.stream_info.update_frame_info(&frame);
+
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        frame.write(&mut sink).map_err(io_error)?;
+        self.output.write_all(sink.as_slice())?;
+        self.samples.clear();
+
+        Ok(())
+    }
+
+    /// Encodes the last block and rewrites the stream info. Must be called
+    /// after the WAV writer was finalized. Returns the output.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.samples.is_empty() {
+            self.encode_block()?;
+        }
+        let md5 = self.frame.1.md5_digest();
+        self.stream_info.set_md5_digest(&md5);
+
+        self.output.seek(SeekFrom::Start(0))?;
+        self.write_stream_info()?;
+        self.output.flush()?;
+
+        Ok(self.output)
+    }
+}
+
+impl<W> Write for FlacEncoder<W>
+where
+    W: Write + Seek,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = match self.data_start {
+            Some(start) if self.position >= start => {
+                if self.position != self.len {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        "FLAC encoder cannot overwrite samples.",
+                    ));
+                }
+                self.push(buf)?;
+                buf.len()
+            }
+            // Header updates with the final chunk sizes are not needed.
+            Some(start) => buf.len().min((start - self.position) as usize),
+            None => {
+                if self.position != self.header.len() as u64 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        "FLAC encoder requires a sequential WAV header.",
+                    ));
+                }
+                self.header.extend_from_slice(buf);
+                if let Some(start) = self.find_data() {
+                    self.data_start = Some(start);
+                    let samples = self.header.split_off(start as usize);
+                    self.push(&samples)?;
+                }
+                buf.len()
+            }
+        };
+
+        self.position += len as u64;
+        self.len = self.len.max(self.position);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W> Seek for FlacEncoder<W>
+where
+    W: Write + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::Current(x) => self.position as i64 + x,
+            SeekFrom::End(x) => self.len as i64 + x,
+        };
+        if position < 0 || position as u64 > self.len {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek outside of the stream.",
+            ));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl<W> std::fmt::Debug for FlacEncoder<W>
+where
+    W: Write + Seek,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("FlacEncoder")
+            .field("bits", &self.bits)
+            .field("channels", &self.channels)
+            .field("position", &self.position)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// Output stream of a WAV file or a FLAC file which is encoded while the
+/// WAV stream is written. [`Output::finish`] completes the FLAC file.
+#[derive(Debug)]
+pub enum Output {
+    Wav(BufWriter<File>),
+    Flac(Box<FlacEncoder<BufWriter<File>>>),
+}
+
+impl Output {
+    /// Creates an output file for a stream with the given WAV spec.
+    pub fn create(
+        filename: &str,
+        format: Format,
+        spec: WavSpec,
+    ) -> Result<Self, Error> {
+        if format == Format::Flac
+            && (spec.sample_format == SampleFormat::Float
+                || spec.bits_per_sample > FLAC_MAX_BITS)
+        {
+            return Err(Error::InvalidArgument(format!(
+                "FLAC requires integer samples with at most {} bits.",
+                FLAC_MAX_BITS
+            )));
+        }
+
+        let file = BufWriter::new(File::create(filename)?);
+        Ok(match format {
+            Format::Wav => Self::Wav(file),
+            Format::Flac => Self::Flac(Box::new(FlacEncoder::new(file, spec)?)),
+        })
+    }
+
+    /// Flushes the file and completes FLAC files. Must be called after
+    /// the WAV writer was finalized.
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            Self::Wav(mut file) => file.flush()?,
+            Self::Flac(encoder) => encoder.finish()?.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Wav(file) => file.write(buf),
+            Self::Flac(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Wav(file) => file.flush(),
+            Self::Flac(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl Seek for Output {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::Wav(file) => file.seek(pos),
+            Self::Flac(encoder) => encoder.seek(pos),
+        }
+    }
+}
+
+/// Numbered output filenames, e.g. "out_1.wav", "out_2.wav".
+pub fn numbered_filenames(filename: &str, count: usize) -> Vec<String> {
+    let path = std::path::Path::new(filename);
+    let stem = path.with_extension("");
+    let extension = path.extension().and_then(|x| x.to_str());
+    (1..=count)
+        .map(|i| match extension {
+            Some(extension) => {
+                format!("{}_{}.{}", stem.display(), i, extension)
+            }
+            None => format!("{}_{}", stem.display(), i),
+        })
+        .collect()
+}
+
+#[test]
+fn test_flac_round_trip() {
+    for bits in [8_u16, 16, 24] {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: bits,
+            sample_format: SampleFormat::Int,
+        };
+        let max = (1_i32 << (bits - 1)) - 1;
+        let samples: Vec<i32> = (0..20000)
+            .map(|i| {
+                let x = (i as f64 * 0.01).sin() + 0.1 * (i as f64 * 1.7).cos();
+                ((x / 1.1 * max as f64) as i32).clamp(-max - 1, max)
+            })
+            .collect();
+
+        let mut encoder =
+            FlacEncoder::new(Cursor::new(Vec::new()), spec).unwrap();
+        let mut writer = WavWriter::new(&mut encoder, spec).unwrap();
+        for x in &samples {
+            writer.write_sample(*x).unwrap();
+        }
+        writer.finalize().unwrap();
+        let mut flac = encoder.finish().unwrap();
+
+        flac.set_position(0);
+        let info = claxon::FlacReader::new(&mut flac).unwrap().streaminfo();
+        assert_eq!(info.samples, Some(10000));
+        assert_eq!(info.bits_per_sample, bits as u32);
+
+        flac.set_position(0);
+        let mut decoded =
+            WavReader::new(FlacDecoder::new(flac).unwrap()).unwrap();
+        assert_eq!(decoded.spec(), spec);
+        assert_eq!(decoded.duration(), 10000);
+        let read = |decoded: &mut WavReader<FlacDecoder<_>>| {
+            decoded
+                .samples::<i32>()
+                .collect::<Result<Vec<i32>, hound::Error>>()
+                .unwrap()
+        };
+        assert_eq!(read(&mut decoded), samples);
+
+        // Seeking restarts the decoder for blocks which were already read.
+        decoded.seek(7000).unwrap();
+        assert_eq!(read(&mut decoded), samples[14000..]);
+        decoded.seek(0).unwrap();
+        assert_eq!(read(&mut decoded), samples);
+    }
+}
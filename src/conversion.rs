@@ -15,7 +15,8 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use hound::{Error, SampleFormat, WavReader, WavSamples};
+use crate::codec;
+use hound::{Error, SampleFormat, WavReader, WavSamples, WavSpec, WavWriter};
 
 pub struct IntoF32Samples<'a, R> {
     samples: WavSamples<'a, R, i32>,
@@ -66,3 +67,330 @@ where
         }
     }
 }
+
+/// Output sample format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Signed integer samples
+    Int,
+    /// 32 bit float samples
+    Float,
+}
+
+/// Dither which is added before quantizing to integer samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dither {
+    /// Round without dither
+    None,
+    /// Triangular probability density function dither
+    Tpdf,
+    /// TPDF dither with noise shaping towards high frequencies
+    Shaped,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// Output sample format, defaults to the input format.
+    /// FLAC output requires integer samples.
+    #[arg(long, value_enum)]
+    sample_format: Option<Format>,
+    /// Output bit depth, defaults to the input bit depth
+    #[arg(long)]
+    bits_per_sample: Option<u16>,
+    /// Dither for integer output, defaults to TPDF dither if the bit depth
+    /// is reduced or the input has float samples.
+    #[arg(long, value_enum)]
+    dither: Option<Dither>,
+}
+
+impl Settings {
+    /// Returns the output spec for the given input spec and file format.
+    pub fn spec(
+        &self,
+        input: WavSpec,
+        file_format: codec::Format,
+    ) -> Result<WavSpec, crate::error::Error> {
+        let format = match (self.sample_format, input.sample_format) {
+            (Some(x), _) => x,
+            (None, SampleFormat::Int) => Format::Int,
+            (None, SampleFormat::Float) => match file_format {
+                codec::Format::Wav => Format::Float,
+                codec::Format::Flac => Format::Int,
+            },
+        };
+        let bits_per_sample = match (self.bits_per_sample, format) {
+            (Some(x), _) => x,
+            (None, Format::Int) => match input.sample_format {
+                SampleFormat::Int => input.bits_per_sample,
+                SampleFormat::Float => 24,
+            },
+            (None, Format::Float) => 32,
+        };
+
+        let invalid =
+            |e: &str| Err(crate::error::Error::InvalidArgument(e.to_string()));
+        match format {
+            Format::Int if !(8..=32).contains(&bits_per_sample) => {
+                invalid("Integer bit depth must be within 8 and 32 bits.")
+            }
+            Format::Float if bits_per_sample != 32 => {
+                invalid("Float samples must have 32 bits.")
+            }
+            Format::Float if file_format == codec::Format::Flac => {
+                invalid("FLAC does not support float samples.")
+            }
+            Format::Int
+                if self.dither == Some(Dither::Shaped)
+                    && ![44100, 48000].contains(&input.sample_rate) =>
+            {
+                invalid("Noise shaping requires 44.1 or 48 kHz sample rate.")
+            }
+            Format::Int => Ok(WavSpec {
+                bits_per_sample,
+                sample_format: SampleFormat::Int,
+                ..input
+            }),
+            Format::Float => Ok(WavSpec {
+                bits_per_sample,
+                sample_format: SampleFormat::Float,
+                ..input
+            }),
+        }
+    }
+
+    /// Returns the dither for the conversion from the input to the output
+    /// spec. Integer samples without bit depth reduction are not dithered,
+    /// so they are written unchanged.
+    pub fn dither(&self, input: WavSpec, output: WavSpec) -> Dither {
+        match self.dither {
+            Some(x) => x,
+            None if input.sample_format == SampleFormat::Float
+                || input.bits_per_sample > output.bits_per_sample =>
+            {
+                Dither::Tpdf
+            }
+            None => Dither::None,
+        }
+    }
+}
+
+/// E-weighted noise shaping filter for 44.1 kHz by Wannamaker,
+/// also close enough for 48 kHz
+const NOISE_SHAPING: [f64; 5] = [2.033, -2.165, 1.959, -1.590, 0.6149];
+
+/// Quantizer from float to integer samples with optional dither.
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    /// Dither type
+    dither: Dither,
+    /// Full scale amplitude in LSB
+    amplitude: f64,
+    /// Past quantization errors of each channel, most recent first
+    errors: Vec<[f64; NOISE_SHAPING.len()]>,
+    /// Xorshift random number generator state
+    state: u64,
+}
+
+impl Quantizer {
+    pub fn new(bits_per_sample: u16, channels: usize, dither: Dither) -> Self {
+        Self {
+            dither,
+            amplitude: 2.0_f64.powi(bits_per_sample as i32 - 1),
+            errors: vec![[0.0; NOISE_SHAPING.len()]; channels],
+            state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+
+    /// Uniformly distributed random number within [-0.5; 0.5[.
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1_u64 << 53) as f64 - 0.5
+    }
+
+    /// Quantizes a sample of the given channel. Returns the integer value
+    /// and whether it was clipped.
+    pub fn quantize(&mut self, channel: usize, x: f32) -> (i32, bool) {
+        let mut value = x as f64 * self.amplitude;
+        if self.dither == Dither::Shaped {
+            value -= NOISE_SHAPING
+                .iter()
+                .zip(self.errors[channel].iter())
+                .map(|(h, e)| h * e)
+                .sum::<f64>();
+        }
+        let dither = match self.dither {
+            Dither::None => 0.0,
+            Dither::Tpdf | Dither::Shaped => self.uniform() + self.uniform(),
+        };
+
+        let quantized = (value + dither).round();
+        if self.dither == Dither::Shaped {
+            let errors = &mut self.errors[channel];
+            errors.rotate_right(1);
+            errors[0] = quantized - value;
+        }
+
+        let clipped = quantized.clamp(-self.amplitude, self.amplitude - 1.0);
+        (clipped as i32, clipped != quantized)
+    }
+}
+
+/// Writes float frames to a WAV writer of any sample format and counts
+/// clipped samples.
+pub struct FrameWriter<W>
+where
+    W: std::io::Write + std::io::Seek,
+{
+    writer: WavWriter<W>,
+    /// Quantizer for integer output
+    quantizer: Option<Quantizer>,
+    /// Number of clipped samples
+    clipped: usize,
+}
+
+impl<W> FrameWriter<W>
+where
+    W: std::io::Write + std::io::Seek,
+{
+    pub fn new(writer: WavWriter<W>, dither: Dither) -> Self {
+        let spec = writer.spec();
+        let quantizer = match spec.sample_format {
+            SampleFormat::Int => Some(Quantizer::new(
+                spec.bits_per_sample,
+                spec.channels as usize,
+                dither,
+            )),
+            SampleFormat::Float => None,
+        };
+
+        Self {
+            writer,
+            quantizer,
+            clipped: 0,
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &[f32]) -> Result<(), Error> {
+        for (i, x) in frame.iter().enumerate() {
+            match &mut self.quantizer {
+                Some(quantizer) => {
+                    let (x, clipped) = quantizer.quantize(i, *x);
+                    self.clipped += clipped as usize;
+                    self.writer.write_sample(x)?;
+                }
+                None => {
+                    // Float samples are not clipped but may exceed
+                    // full scale.
+                    self.clipped += (x.abs() > 1.0) as usize;
+                    self.writer.write_sample(*x)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finalizes the WAV file and returns the number of clipped samples.
+    pub fn finalize(self) -> Result<usize, Error> {
+        self.writer.finalize()?;
+        Ok(self.clipped)
+    }
+}
+
+#[test]
+fn test_quantizer() {
+    // Dither free quantization restores integer input.
+    let mut quantizer = Quantizer::new(16, 1, Dither::None);
+    for x in [-32768, -1, 0, 1, 12345, 32767] {
+        let (y, clipped) = quantizer.quantize(0, x as f32 / 32768.0);
+        assert_eq!((y, clipped), (x, false));
+    }
+    assert_eq!(quantizer.quantize(0, 1.5), (32767, true));
+    assert_eq!(quantizer.quantize(0, -1.5), (-32768, true));
+
+    // Dither is unbiased and limited to +-1 LSB for TPDF.
+    for dither in [Dither::Tpdf, Dither::Shaped] {
+        let mut quantizer = Quantizer::new(16, 1, dither);
+        let n = 100000;
+        let x = 0.25 / 32768.0;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let (y, _) = quantizer.quantize(0, x);
+            if dither == Dither::Tpdf {
+                assert!((-1..=1).contains(&y));
+            }
+            sum += y as f64;
+        }
+        assert!((sum / n as f64 - 0.25).abs() < 0.01);
+    }
+}
+
+#[test]
+fn test_passthrough() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let settings = Settings {
+        sample_format: None,
+        bits_per_sample: None,
+        dither: None,
+    };
+    let output = settings.spec(spec, codec::Format::Wav).unwrap();
+    assert_eq!(output, spec);
+    assert_eq!(settings.dither(spec, output), Dither::None);
+    let float = WavSpec {
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+        ..spec
+    };
+    assert_eq!(settings.dither(float, spec), Dither::Tpdf);
+    let reduced = WavSpec {
+        bits_per_sample: 24,
+        ..spec
+    };
+    assert_eq!(settings.dither(reduced, spec), Dither::Tpdf);
+    let shaped = Settings {
+        dither: Some(Dither::Shaped),
+        ..settings.clone()
+    };
+    let high_rate = WavSpec {
+        sample_rate: 96000,
+        ..spec
+    };
+    assert!(shaped.spec(high_rate, codec::Format::Wav).is_err());
+
+    // 16 bit input is written bit-identical to 16 bit output.
+    let samples: Vec<i16> = (0..20000)
+        .map(|i| (30000.0 * (i as f64 * 0.001).sin()) as i16 + (i % 3) as i16)
+        .collect();
+    let mut input = std::io::Cursor::new(Vec::new());
+    let mut writer = WavWriter::new(&mut input, spec).unwrap();
+    for x in &samples {
+        writer.write_sample(*x).unwrap();
+    }
+    writer.finalize().unwrap();
+    input.set_position(0);
+    let mut input = hound::WavReader::new(input).unwrap();
+
+    let mut wav = std::io::Cursor::new(Vec::new());
+    let writer = WavWriter::new(&mut wav, output).unwrap();
+    let mut writer = FrameWriter::new(writer, settings.dither(spec, output));
+    let frames: Vec<f32> = input.samples_f32().map(|x| x.unwrap()).collect();
+    for frame in frames.chunks(2) {
+        writer.write_frame(frame).unwrap();
+    }
+    assert_eq!(writer.finalize().unwrap(), 0);
+
+    wav.set_position(0);
+    let output: Vec<i16> = hound::WavReader::new(wav)
+        .unwrap()
+        .samples::<i16>()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(output, samples);
+}
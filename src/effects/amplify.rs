@@ -15,47 +15,41 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use crate::conversion::Conversion;
+use super::{mid_side::Target, Effect};
+use crate::conversion::FrameWriter;
 use crate::error::Error;
-use crate::frame::FrameIterator;
-use crate::progress::Progress;
-use hound::{WavReader, WavWriter};
+use hound::WavReader;
 
-#[derive(Debug, Clone, clap::Args)]
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+#[command(allow_negative_numbers = true)]
 pub struct Settings {
     /// Gain in dB to apply to each channel.
+    #[arg(required = true)]
     gain_db: Vec<f32>,
+    /// Amplify all channels or only the mid or side signal of stereo input
+    #[arg(long, value_enum, default_value_t = Target::Stereo)]
+    #[serde(default)]
+    target: Target,
 }
 
 impl Settings {
     pub fn new(gain_db: Vec<f32>) -> Self {
-        Self { gain_db }
+        Self {
+            gain_db,
+            target: Target::Stereo,
+        }
     }
 
-    pub fn amplify<R, W>(
-        &self,
-        input: &mut WavReader<R>,
-        output: &mut WavWriter<W>,
-    ) -> Result<(), Error>
-    where
-        R: std::io::Read + std::io::Seek,
-        W: std::io::Write + std::io::Seek,
-    {
-        let spec = input.spec();
-        let duration = input.duration();
-        let mut progress =
-            Progress::new(duration as usize, "Processing sample");
-        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
-
-        let gain: Vec<f32> = if usize::from(spec.channels) == self.gain_db.len()
-        {
+    pub fn build(&self, channels: usize) -> Result<Box<dyn Effect>, Error> {
+        let channels = self.target.channels(channels)?;
+        let gain: Vec<f32> = if channels == self.gain_db.len() {
             self.gain_db
                 .iter()
                 .map(|x| 10.0_f32.powf(x / 20.0))
                 .collect()
         } else if self.gain_db.len() == 1 {
             std::iter::repeat(10.0_f32.powf(self.gain_db[0] / 20.0))
-                .take(spec.channels as usize)
+                .take(channels)
                 .collect()
         } else {
             return Err(Error::InvalidArgument(
@@ -63,18 +57,50 @@ impl Settings {
             ));
         };
 
-        while let Some(frame) = frames.next() {
-            progress.next();
-            match frame {
-                Ok(frame) => {
-                    for (i, sample) in frame.iter().enumerate() {
-                        output.write_sample(sample * gain[i])?;
-                    }
-                }
-                Err(e) => return Err(e.into()),
-            }
+        Ok(self.target.wrap(Box::new(Amplifier { gain })))
+    }
+
+    pub fn amplify<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut amplifier = self.build(input.spec().channels as usize)?;
+        super::apply(amplifier.as_mut(), input, output, "Processing sample")
+    }
+}
+
+/// Constant gain amplifier
+#[derive(Debug, Clone)]
+pub struct Amplifier {
+    /// Linear gain of each channel
+    gain: Vec<f32>,
+}
+
+impl Effect for Amplifier {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.gain.len() {
+            return Err(Error::InvalidFrame);
         }
 
-        Ok(())
+        Ok(frame
+            .iter()
+            .zip(self.gain.iter())
+            .map(|(x, g)| x * g)
+            .collect())
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {}
+
+    fn output_channels(&self) -> usize {
+        self.gain.len()
     }
 }
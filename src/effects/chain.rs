@@ -0,0 +1,386 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{
+    amplify, compressor, envelope, equalizer, gate, gate::Gate, limiter,
+    limiter::Limiter, mid_side, multiband, width, Effect,
+};
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use crate::frame::ChannelMap;
+use crate::operations::normalize;
+use clap::Parser;
+use hound::WavReader;
+
+/// One processing step of an effect chain.
+#[derive(Debug, Clone, clap::Subcommand, serde::Deserialize)]
+#[serde(tag = "effect", rename_all = "kebab-case")]
+pub enum Step {
+    /// Amplifier
+    Amplify(amplify::Settings),
+    /// Dynamic compression
+    Compressor(compressor::Settings),
+    /// Gain envelope from a file
+    Envelope(envelope::Settings),
+    /// Parametric equalizer
+    Equalizer(equalizer::Settings),
+    /// Noise gate and downward expander
+    Gate(gate::Settings),
+    /// True peak brickwall limiter
+    Limiter(limiter::Settings),
+    /// Mid/side encoder or decoder
+    MidSide(mid_side::Settings),
+    /// Multiband compressor
+    Multiband(multiband::Settings),
+    /// Stereo width control
+    Width(width::Settings),
+    /// Normalize the output of the previous steps
+    Normalize(normalize::Settings),
+}
+
+/// Command line parser for a single step.
+#[derive(Debug, Parser)]
+#[command(name = "step")]
+struct StepParser {
+    #[command(subcommand)]
+    step: Step,
+}
+
+impl Step {
+    /// Constructs the effect of this step for the given input.
+    /// Normalize steps are constructed with unity gain,
+    /// [`Settings::process`] measures the actual gain.
+    pub fn build(
+        &self,
+        fs: f64,
+        channels: usize,
+    ) -> Result<Box<dyn Effect>, Error> {
+        Ok(match self {
+            Self::Amplify(x) => x.build(channels)?,
+            Self::Compressor(x) => x.build(fs, channels)?,
+            Self::Envelope(x) => Box::new(x.build(fs, channels)?),
+            Self::Equalizer(x) => Box::new(x.build(fs, channels)?),
+            Self::Gate(x) => Box::new(Gate::new(fs, channels, x)?),
+            Self::Limiter(x) => Box::new(Limiter::new(fs, channels, x)?),
+            Self::MidSide(x) => Box::new(x.build(channels)?),
+            Self::Multiband(x) => Box::new(x.build(fs, channels)?),
+            Self::Width(x) => Box::new(x.build(channels)?),
+            Self::Normalize(x) => {
+                Box::new(Chain::new(x.build(&[0.0], fs, channels)?, channels))
+            }
+        })
+    }
+
+    /// Effect name as used on the command line and in presets.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Amplify(_) => "amplify",
+            Self::Compressor(_) => "compressor",
+            Self::Envelope(_) => "envelope",
+            Self::Equalizer(_) => "equalizer",
+            Self::Gate(_) => "gate",
+            Self::Limiter(_) => "limiter",
+            Self::MidSide(_) => "mid-side",
+            Self::Multiband(_) => "multiband",
+            Self::Width(_) => "width",
+            Self::Normalize(_) => "normalize",
+        }
+    }
+
+    /// Wraps an error with the position and name of this step.
+    fn error(&self, index: usize, error: Error) -> Error {
+        Error::Step {
+            index,
+            name: self.name(),
+            error: Box::new(error),
+        }
+    }
+}
+
+impl std::str::FromStr for Step {
+    type Err = String;
+
+    /// Parses a step from its command line arguments,
+    /// e.g. "limiter -1 0.005 0.1".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StepParser::try_parse_from(
+            std::iter::once("step").chain(s.split_whitespace()),
+        )
+        .map(|x| x.step)
+        .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Effects to apply in the given order, each as one argument with the
+    /// effect name and its parameters, e.g. "limiter -1 0.005 0.1"
+    #[arg(required = true)]
+    steps: Vec<Step>,
+}
+
+impl Settings {
+    #[cfg(test)]
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self { steps }
+    }
+
+    /// Loads the settings from a TOML or JSON preset file.
+    pub fn load(filename: &str) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(filename)?;
+        match std::path::Path::new(filename)
+            .extension()
+            .and_then(|x| x.to_str())
+        {
+            Some("toml") => Ok(toml::from_str(&content)?),
+            Some("json") => Ok(serde_json::from_str(&content)?),
+            _ => Err(Error::InvalidArgument(format!(
+                "Preset '{}' must be a .toml or .json file.",
+                filename
+            ))),
+        }
+    }
+
+    /// Constructs the effect chain for the given input.
+    /// Each step is configured with the output channel count
+    /// of the previous step.
+    pub fn build(&self, fs: f64, channels: usize) -> Result<Chain, Error> {
+        let mut effects = Vec::with_capacity(self.steps.len());
+        let mut step_channels = channels;
+        for (i, step) in self.steps.iter().enumerate() {
+            let effect = step
+                .build(fs, step_channels)
+                .map_err(|e| step.error(i, e))?;
+            step_channels = effect.output_channels();
+            effects.push(effect);
+        }
+
+        Ok(Chain::new(effects, channels))
+    }
+
+    /// Checks all step parameters for the given input and returns
+    /// the number of output channels.
+    pub fn validate(&self, fs: f64, channels: usize) -> Result<usize, Error> {
+        Ok(self.build(fs, channels)?.output_channels())
+    }
+
+    /// Applies the chain to the input. The gain of each normalize step
+    /// is measured in a separate pass over the output of all previous
+    /// steps. The layout gives the speaker position of every input
+    /// channel.
+    pub fn process<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+        layout: &[ChannelMap],
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let fs = spec.sample_rate as f64;
+        let channels = spec.channels as usize;
+        self.validate(fs, channels)?;
+
+        let mut effects: Vec<Box<dyn Effect>> = Vec::new();
+        let mut step_channels = channels;
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                Step::Normalize(x) => {
+                    let mut measurement = if step_channels == layout.len() {
+                        x.measurement(fs, layout)
+                    } else {
+                        let layout = ChannelMap::default_layout(step_channels);
+                        x.measurement(fs, &layout)
+                    };
+                    let mut chain = Chain::new(effects, channels);
+                    input.seek(0)?;
+                    super::apply_to(
+                        &mut chain,
+                        input,
+                        |frame| measurement.process(frame),
+                        "Analyzing sample",
+                    )
+                    .map_err(|e| step.error(i, e))?;
+
+                    let gain_db =
+                        x.gain_db(measurement).map_err(|e| step.error(i, e))?;
+                    effects = chain.effects;
+                    effects.extend(
+                        x.build(&gain_db, fs, step_channels)
+                            .map_err(|e| step.error(i, e))?,
+                    );
+                }
+                _ => effects.push(
+                    step.build(fs, step_channels)
+                        .map_err(|e| step.error(i, e))?,
+                ),
+            }
+            step_channels = effects
+                .last()
+                .map_or(step_channels, |x| x.output_channels());
+        }
+
+        input.seek(0)?;
+        let mut chain = Chain::new(effects, channels);
+        super::apply(&mut chain, input, output, "Processing sample")
+    }
+}
+
+/// Command line arguments to load the chain from a preset file.
+#[derive(Debug, Clone, clap::Args)]
+pub struct Preset {
+    /// TOML or JSON preset file with a list of steps,
+    /// see README.md for the format
+    filename: String,
+}
+
+impl Preset {
+    pub fn load(&self) -> Result<Settings, Error> {
+        Settings::load(&self.filename)
+    }
+}
+
+/// Sequence of effects which are applied one after another.
+#[derive(Debug)]
+pub struct Chain {
+    /// Effects in processing order
+    effects: Vec<Box<dyn Effect>>,
+    /// Number of output channels
+    channels: usize,
+}
+
+impl Chain {
+    /// Constructs a chain from already configured effects and the number
+    /// of input channels.
+    pub fn new(effects: Vec<Box<dyn Effect>>, channels: usize) -> Self {
+        let channels = effects.last().map_or(channels, |x| x.output_channels());
+        Self { effects, channels }
+    }
+}
+
+impl Effect for Chain {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        let mut frame = frame.to_vec();
+        for effect in self.effects.iter_mut() {
+            frame = effect.process(&frame)?;
+        }
+        Ok(frame)
+    }
+
+    fn latency(&self) -> usize {
+        self.effects.iter().map(|x| x.latency()).sum()
+    }
+
+    fn reset(&mut self) {
+        for effect in self.effects.iter_mut() {
+            effect.reset();
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_chain_latency_compensation() {
+    let fs = 48000.0;
+    let steps: Vec<Step> = ["amplify -6", "limiter 0 0.001 0.1", "amplify 6"]
+        .iter()
+        .map(|x| x.parse().unwrap())
+        .collect();
+    let mut chain = Settings::new(steps).build(fs, 1).unwrap();
+    let latency = chain.latency();
+    assert_eq!(latency, 47 + 6);
+
+    // Impulse is delayed by the sum of all latencies and passes unchanged.
+    let output: Vec<f32> = (0..2 * latency)
+        .map(|i| if i == 10 { 0.5 } else { 0.0 })
+        .map(|x| chain.process(&[x]).unwrap()[0])
+        .collect();
+    for (i, x) in output.iter().enumerate() {
+        let expected = if i == 10 + latency { 0.5 } else { 0.0 };
+        assert!((x - expected).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_preset_validation() {
+    let preset = r#"
+        [[steps]]
+        effect = "equalizer"
+        bands = ["high-pass:40:0.707"]
+
+        [[steps]]
+        effect = "compressor"
+        detector = "peak"
+        threshold_db = -20
+        ratio = 1
+        knee_width_db = 0
+        attack_time = 0.01
+        release_time = 0.1
+        lookahead_time = 0.005
+        hold_time = 0
+        output_gain_db = 0
+
+        [[steps]]
+        effect = "normalize"
+        mode = "lufs"
+        target_db = -23
+    "#;
+    let settings: Settings = toml::from_str(preset).unwrap();
+    assert_eq!(settings.steps.len(), 3);
+    match settings.validate(48000.0, 2) {
+        Err(Error::Step { index, name, .. }) => {
+            assert_eq!(index, 1);
+            assert_eq!(name, "compressor");
+        }
+        x => panic!("Unexpected validation result: {:?}", x),
+    }
+}
+
+#[test]
+fn test_step_validation() {
+    let valid = [
+        "limiter -1 0.005 0.1",
+        "normalize lufs-true-peak -16 -l",
+        "gate peak -40 10 20 0.001 0.05 0.01 0.002 3",
+    ];
+    let invalid = [
+        "limiter 1 0.005 0.1",
+        "limiter -1 -0.005 0.1",
+        "limiter -1 0.005 -0.1",
+        "normalize lufs inf",
+        "normalize lufs-true-peak -16 -l --lookahead-time -1",
+        "compressor peak -20 3 0 -0.01 0.1 0.005 0 0",
+        "gate peak -40 10 20 0.001 -0.05 0.01 0.002 3",
+    ];
+    for (step, ok) in valid
+        .iter()
+        .map(|x| (x, true))
+        .chain(invalid.iter().map(|x| (x, false)))
+    {
+        let steps = vec!["amplify -6".parse().unwrap(), step.parse().unwrap()];
+        match Settings::new(steps).validate(48000.0, 2) {
+            Ok(2) if ok => (),
+            Err(Error::Step { index: 1, .. }) if !ok => (),
+            x => panic!("Unexpected validation result of {}: {:?}", step, x),
+        }
+    }
+}
@@ -15,15 +15,23 @@
     You should have received a copy of the GNU Affero General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
-use crate::conversion::Conversion;
+use super::{
+    mid_side::{Target, Targeted},
+    Effect,
+};
+use crate::codec;
+use crate::conversion::{Conversion, FrameWriter};
 use crate::error::Error;
-use crate::filters::{lag1::Lag1, mov_max::MovMax, mov_rms::MovRms, Filter};
+use crate::filters::{
+    biquad::Biquad, lag1::Lag1, mov_max::MovMax, mov_rms::MovRms, Filter,
+};
 use crate::frame::FrameIterator;
 use crate::progress::Progress;
-use hound::{WavReader, WavWriter};
+use hound::WavReader;
 use std::collections::VecDeque;
 
-#[derive(Debug, Clone, clap::ValueEnum)]
+#[derive(Debug, Clone, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PeakDetector {
     /// Sliding maximum detector
     Peak,
@@ -31,13 +39,20 @@ pub enum PeakDetector {
     Rms,
 }
 
-#[derive(Debug, clap::Args)]
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+#[command(allow_negative_numbers = true)]
 pub struct Settings {
     /// Peak detector to use
     detector: PeakDetector,
-    /// Compress stereo channels independently
+    /// Compress stereo channels independently, same as a link of zero
     #[arg(short)]
+    #[serde(default)]
     stereo_indep: bool,
+    /// Stereo link in percent between independent (0) and fully linked
+    /// (100) gain of the channels
+    #[arg(long, default_value_t = 100.0)]
+    #[serde(default = "default_link")]
+    link: f64,
     /// Compressor threshold in dB
     threshold_db: f64,
     /// Compression ratio. Must be greater than one.
@@ -54,83 +69,199 @@ pub struct Settings {
     hold_time: f64,
     /// Compressor output gain in dB.
     output_gain_db: f64,
+    /// Compress all channels or only the mid or side signal of stereo input
+    #[arg(long, value_enum, default_value_t = Target::Stereo)]
+    #[serde(default)]
+    target: Target,
+    /// Sidechain file which feeds the detector instead of the input, it must
+    /// be mono or have the same number of channels as the input.
+    #[arg(long)]
+    #[serde(skip)]
+    sidechain: Option<String>,
+    /// Cutoff frequency in Hz of the high-pass filter in front of the
+    /// detector, e.g. to avoid pumping by the kick drum
+    #[arg(long)]
+    #[serde(default)]
+    sidechain_filter: Option<f64>,
+}
+
+fn default_link() -> f64 {
+    100.0
 }
 
 impl Settings {
+    /// Constructs the compressor for the targeted signal, e.g. as chain step.
+    /// Unlike [`Settings::compress`], the envelope is not preset from the
+    /// beginning of the input, so it starts without gain reduction and
+    /// settles within the attack time.
+    pub fn build(
+        &self,
+        fs: f64,
+        channels: usize,
+    ) -> Result<Box<dyn Effect>, Error> {
+        if self.sidechain.is_some() {
+            return Err(Error::InvalidArgument(
+                "Sidechain input is only supported by the compressor command."
+                    .into(),
+            ));
+        }
+        let channels = self.target.channels(channels)?;
+        let compressor = Compressor::new(fs, channels, self)?;
+        Ok(self.target.wrap(Box::new(compressor)))
+    }
+
     pub fn compress<R, W>(
         &self,
         input: &mut WavReader<R>,
-        output: &mut WavWriter<W>,
+        output: &mut FrameWriter<W>,
     ) -> Result<(), Error>
     where
         R: std::io::Read + std::io::Seek,
         W: std::io::Write + std::io::Seek,
     {
         let spec = input.spec();
-        let duration = input.duration();
 
-        // TODO: handle channel independent flag
         let mut compressor = Compressor::new(
             spec.sample_rate as f64,
-            spec.channels as usize,
-            &self,
-        );
+            self.target.channels(spec.channels as usize)?,
+            self,
+        )?;
+
+        if let Some(filename) = &self.sidechain {
+            let mut sidechain = codec::open(filename)?;
+            return self.compress_sidechain(
+                compressor,
+                input,
+                &mut sidechain,
+                output,
+            );
+        }
 
-        Self::compensate_initial_condition(
+        self.compensate_initial_condition(
             &mut compressor,
             input,
             spec.channels,
             spec.sample_rate as f64,
-            self.attack_time,
         )?;
         input.seek(0)?;
 
-        let mut counter = 0;
+        let mut compressor = self.target.wrap(Box::new(compressor));
+        super::apply(compressor.as_mut(), input, output, "Compressing sample")
+    }
+
+    /// Compresses the input with the gain which is detected from the
+    /// sidechain input.
+    fn compress_sidechain<R, S, W>(
+        &self,
+        mut compressor: Compressor,
+        input: &mut WavReader<R>,
+        sidechain: &mut WavReader<S>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        S: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let sidechain_spec = sidechain.spec();
+        if sidechain_spec.sample_rate != spec.sample_rate {
+            return Err(Error::InvalidArgument(format!(
+                "Sidechain sample rate {} Hz differs from input {} Hz.",
+                sidechain_spec.sample_rate, spec.sample_rate
+            )));
+        }
+        if sidechain_spec.channels != 1
+            && sidechain_spec.channels != spec.channels
+        {
+            return Err(Error::InvalidArgument(
+                "Sidechain must be mono or have the input channels.".into(),
+            ));
+        }
+        if sidechain.duration() < input.duration() {
+            return Err(Error::InvalidArgument(
+                "Sidechain is shorter than the input.".into(),
+            ));
+        }
+
+        self.compensate_initial_condition(
+            &mut compressor,
+            sidechain,
+            sidechain_spec.channels,
+            spec.sample_rate as f64,
+        )?;
+        sidechain.seek(0)?;
+
+        let latency = compressor.latency();
+        let mut compressor = Targeted::new(self.target, Box::new(compressor));
+        let mut skip = latency;
+        let mut write = |frame: Vec<f32>| -> Result<(), Error> {
+            // Compensate lookahead latency
+            if skip > 0 {
+                skip -= 1;
+                return Ok(());
+            }
+            Ok(output.write_frame(&frame)?)
+        };
+
         let mut progress =
-            Progress::new(duration as usize, "Compressing sample");
+            Progress::new(input.duration() as usize, "Compressing sample");
         let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        let mut sidechain_frames = FrameIterator::new(
+            sidechain.samples_f32(),
+            sidechain_spec.channels,
+        );
         while let Some(frame) = frames.next() {
             progress.next();
+            let detector = match sidechain_frames.next() {
+                Some(Ok(x)) => self.detector_frame(x),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(Error::InvalidFrame),
+            };
             match frame {
                 Ok(frame) => {
-                    let proc = compressor.process(frame)?;
-                    // Compensate filter latency
-                    if counter > compressor.latency() {
-                        for sample in proc {
-                            output.write_sample(sample)?;
-                        }
-                    } else {
-                        counter += 1;
-                    }
+                    write(compressor.process_with(frame, |c, x| {
+                        c.process_sidechain(x, &detector)
+                    })?)?
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        // Drain processing pipeline
+        // Drain lookahead buffer
         let padding = vec![0.0; spec.channels as usize];
-        for _ in 1..compressor.latency() {
-            let proc = compressor.process(&padding)?;
-            for sample in proc {
-                output.write_sample(sample)?;
-            }
+        let silence =
+            self.detector_frame(&vec![0.0; sidechain_spec.channels as usize]);
+        for _ in 0..latency {
+            write(compressor.process_with(&padding, |c, x| {
+                c.process_sidechain(x, &silence)
+            })?)?;
         }
 
         Ok(())
     }
 
+    /// Returns the detector input for an input or sidechain frame.
+    fn detector_frame(&self, frame: &[f32]) -> Vec<f32> {
+        if frame.len() == 1 {
+            frame.to_vec()
+        } else {
+            self.target.select(frame)
+        }
+    }
+
     fn compensate_initial_condition<R>(
+        &self,
         compressor: &mut Compressor,
         input: &mut WavReader<R>,
         channels: u16,
         fs: f64,
-        attack_time: f64,
     ) -> Result<(), Error>
     where
         R: std::io::Read + std::io::Seek,
     {
         let mut counter = 0;
-        let settling_len = Lag1::settling_len(fs, attack_time);
+        let settling_len = Lag1::settling_len(fs, self.attack_time);
         let mut frames = FrameIterator::new(input.samples_f32(), channels);
         while let Some(frame) = frames.next() {
             if counter > settling_len {
@@ -138,7 +269,7 @@ impl Settings {
             }
             match frame {
                 Ok(frame) => {
-                    compressor.process_initial(frame)?;
+                    compressor.process_initial(&self.detector_frame(frame))?;
                     counter += 1;
                 }
                 Err(e) => return Err(e.into()),
@@ -149,17 +280,27 @@ impl Settings {
     }
 }
 
-/// EBUR128 loudness analyzer
+/// Dynamic range compressor
 #[derive(Debug)]
 pub struct Compressor {
+    /// Sampling rate
+    fs: f64,
+    /// Compressor settings for resetting
+    settings: Settings,
     /// Number of channels
     channels: usize,
-    /// Envelope detector preprocessing filter
-    preprocessor: Box<dyn Filter>,
+    /// Sidechain high-pass filter of each channel
+    filters: Vec<Biquad>,
+    /// Envelope detector preprocessing filter of each channel
+    preprocessors: Vec<Box<dyn Filter>>,
     /// Lookahead in samples, this is also the filter latency.
     lookahead: usize,
-    /// Main envelope detection filter.
+    /// Envelope detection filter of the linked channels.
     envelope: Lag1,
+    /// Envelope detection filters of the independent channels.
+    envelopes: Vec<Lag1>,
+    /// Stereo link between 0 (independent) and 1 (linked).
+    link: f64,
     /// Filter input data buffer.
     buffer: VecDeque<Vec<f32>>,
     /// Compressor threshold in dB.
@@ -173,35 +314,99 @@ pub struct Compressor {
 }
 
 impl Compressor {
-    pub fn new(fs: f64, channels: usize, settings: &Settings) -> Self {
-        let lookahead = (settings.lookahead_time * fs) as usize;
-        let hold = (settings.hold_time * fs) as usize;
-        let preprocessor = match settings.detector {
-            PeakDetector::Peak => {
-                Box::new(MovMax::new((lookahead + hold) * channels))
-                    as Box<dyn Filter>
+    pub fn new(
+        fs: f64,
+        channels: usize,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        if settings.ratio <= 1.0 {
+            return Err(Error::InvalidArgument(
+                "Ratio must be greater than one.".into(),
+            ));
+        }
+
+        if settings.knee_width_db < 0.0 {
+            return Err(Error::InvalidArgument(
+                "Knee width must not be negative.".into(),
+            ));
+        }
+
+        if !(0.0..=100.0).contains(&settings.link) {
+            return Err(Error::InvalidArgument(
+                "Link must be between 0 and 100 percent.".into(),
+            ));
+        }
+
+        let times = [
+            settings.attack_time,
+            settings.release_time,
+            settings.lookahead_time,
+            settings.hold_time,
+        ];
+        if !times.iter().all(|x| *x >= 0.0) {
+            return Err(Error::InvalidArgument(
+                "Attack, release, lookahead and hold time must not be \
+                 negative."
+                    .into(),
+            ));
+        }
+
+        let filters = match settings.sidechain_filter {
+            Some(f0) if f0 <= 0.0 || f0 >= fs / 2.0 => {
+                return Err(Error::InvalidArgument(
+                    "Sidechain filter frequency must be between zero and \
+                     half the sampling rate."
+                        .into(),
+                ))
             }
-            PeakDetector::Rms => {
-                Box::new(MovRms::new(2.0, (lookahead + hold) * channels))
-                    as Box<dyn Filter>
+            Some(f0) => {
+                vec![
+                    Biquad::high_pass(fs, f0, std::f64::consts::FRAC_1_SQRT_2);
+                    channels
+                ]
             }
+            None => Vec::new(),
         };
 
-        Self {
+        let lookahead = (settings.lookahead_time * fs) as usize;
+        let hold = (settings.hold_time * fs) as usize;
+        // The window also covers the sample which leaves the lookahead
+        // buffer so that even single peaks are detected.
+        let window = lookahead + hold + 1;
+        let preprocessors = (0..channels)
+            .map(|_| match settings.detector {
+                PeakDetector::Peak => {
+                    Box::new(MovMax::new(window)) as Box<dyn Filter>
+                }
+                PeakDetector::Rms => {
+                    Box::new(MovRms::new(2.0, window)) as Box<dyn Filter>
+                }
+            })
+            .collect();
+        let envelope = Lag1::new(
+            match settings.detector {
+                PeakDetector::Peak => 1.0,
+                PeakDetector::Rms => 1.0 + (settings.attack_time / 30.0).exp(),
+            },
+            settings.attack_time,
+            settings.release_time,
+            fs,
+        );
+
+        Ok(Self {
+            fs,
+            settings: settings.clone(),
             channels,
-            preprocessor,
+            filters,
+            preprocessors,
             lookahead,
-            envelope: Lag1::new(
-                match settings.detector {
-                    PeakDetector::Peak => 1.0,
-                    PeakDetector::Rms => {
-                        1.0 + (settings.attack_time / 30.0).exp()
-                    }
-                },
-                settings.attack_time,
-                settings.release_time,
-                fs,
-            ),
+            envelopes: vec![envelope.clone(); channels],
+            envelope,
+            link: if settings.stereo_indep {
+                0.0
+            } else {
+                settings.link / 100.0
+            },
             buffer: VecDeque::from(vec![
                 vec![0.0; channels as usize];
                 lookahead
@@ -210,79 +415,412 @@ impl Compressor {
             ratio: settings.ratio,
             knee_width_db: settings.knee_width_db,
             output_gain_db: settings.output_gain_db,
-        }
+        })
     }
 
-    pub fn latency(&self) -> usize {
-        self.lookahead
-    }
-
-    // TODO: add test to validate effect
-    pub fn process(&mut self, frame: &Vec<f32>) -> Result<Vec<f32>, Error> {
-        if self.ratio <= 1.0 {
-            return Err(Error::InvalidArgument(
-                "Ratio must be greater than one.".into(),
-            ));
-        }
-        if frame.len() != self.channels {
+    /// Feeds the detector with a frame of the input or a mono or
+    /// multichannel sidechain.
+    pub fn process_initial(&mut self, frame: &Vec<f32>) -> Result<(), Error> {
+        if frame.len() != 1 && frame.len() != self.channels {
             return Err(Error::InvalidFrame);
         }
+        self.detect(frame);
+
+        Ok(())
+    }
 
-        let mut preproc = 0.0;
-        for x in frame {
-            preproc = self.preprocessor.process(*x as f64);
+    /// Processes one frame with the gain which is detected from the
+    /// sidechain frame. A mono sidechain feeds the detectors of all channels.
+    pub fn process_sidechain(
+        &mut self,
+        frame: &[f32],
+        sidechain: &[f32],
+    ) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels
+            || (sidechain.len() != 1 && sidechain.len() != self.channels)
+        {
+            return Err(Error::InvalidFrame);
         }
 
-        let envelope = self.envelope.process(preproc);
-        let gain = self.gain(envelope) as f32;
+        let gains = self.detect(sidechain);
         // TODO: avoid re-construction of inner vectors
-        let current_frame = self.buffer.pop_front().unwrap();
         self.buffer.push_back(frame.to_owned());
+        let current_frame = self.buffer.pop_front().unwrap();
 
-        Ok(current_frame.iter().map(|x| x * gain).collect())
+        Ok(current_frame
+            .iter()
+            .zip(gains.iter())
+            .map(|(x, gain)| x * gain)
+            .collect())
     }
 
-    pub fn process_initial(&mut self, frame: &Vec<f32>) -> Result<(), Error> {
-        if frame.len() != self.channels {
-            return Err(Error::InvalidFrame);
+    /// Updates the envelope detectors and returns the gain of each channel.
+    fn detect(&mut self, frame: &[f32]) -> Vec<f32> {
+        let mut levels = Vec::with_capacity(self.channels);
+        for channel in 0..self.channels {
+            let x = frame[channel.min(frame.len() - 1)] as f64;
+            let x = match self.filters.get_mut(channel) {
+                Some(filter) => filter.process(x),
+                None => x,
+            };
+            levels.push(self.preprocessors[channel].process(x.abs()));
         }
-        let mut preproc = 0.0;
-        for x in frame {
-            preproc = self.preprocessor.process(*x as f64);
+
+        let linked_gain_db = if self.link > 0.0 {
+            let level = match self.settings.detector {
+                PeakDetector::Peak => {
+                    levels.iter().fold(0.0, |acc: f64, x| acc.max(*x))
+                }
+                PeakDetector::Rms => {
+                    (levels.iter().map(|x| x * x).sum::<f64>()
+                        / levels.len() as f64)
+                        .sqrt()
+                }
+            };
+            let envelope = self.envelope.process(level);
+            self.gain_db(envelope)
+        } else {
+            0.0
+        };
+
+        if self.link >= 1.0 {
+            let gain = 10.0_f64.powf(linked_gain_db / 20.0) as f32;
+            return vec![gain; self.channels];
         }
-        self.envelope.process(preproc);
 
-        Ok(())
+        let mut gains = Vec::with_capacity(self.channels);
+        for (channel, level) in levels.iter().enumerate() {
+            let envelope = self.envelopes[channel].process(*level);
+            let gain_db = self.link * linked_gain_db
+                + (1.0 - self.link) * self.gain_db(envelope);
+            gains.push(10.0_f64.powf(gain_db / 20.0) as f32);
+        }
+        gains
     }
 
-    fn gain(&self, env: f64) -> f64 {
+    /// Gain in dB for the given envelope level. Within the knee, the
+    /// compression is interpolated quadratically.
+    fn gain_db(&self, env: f64) -> f64 {
         let env_db = 20.0 * env.log10();
         // Prevent NaN propagation with a very low dB value if envelope is zero.
         let env_db = if env_db.is_nan() { -200.0 } else { env_db };
 
-        let knee_cond = 2.0 * (env_db - self.threshold_db);
-
-        if knee_cond < self.knee_width_db {
-            // Below threshold: only apply make-up gain
-            10.0_f64.powf(self.output_gain_db / 20.0)
-        } else if knee_cond >= self.knee_width_db {
-            // Above threshold: apply compression and make-up gain
-            10.0_f64.powf(
-                (self.threshold_db
-                    + (env_db - self.threshold_db) / self.ratio
-                    + self.output_gain_db
-                    - env_db)
-                    / 20.0,
-            )
+        let overshoot = env_db - self.threshold_db;
+
+        if 2.0 * overshoot <= -self.knee_width_db {
+            // Below knee: only apply make-up gain
+            self.output_gain_db
+        } else if 2.0 * overshoot >= self.knee_width_db {
+            // Above knee: apply compression and make-up gain
+            (1.0 / self.ratio - 1.0) * overshoot + self.output_gain_db
         } else {
             // Within knee: apply interpolated compression and make-up gain
-            10.0_f64.powf(
-                ((1.0 / self.ratio - 1.0)
-                    * 2.0_f64.powf(
-                        env_db - self.threshold_db + self.knee_width_db / 2.0,
-                    ))
-                    / 20.0,
-            )
+            (1.0 / self.ratio - 1.0)
+                * (overshoot + self.knee_width_db / 2.0).powi(2)
+                / (2.0 * self.knee_width_db)
+                + self.output_gain_db
         }
     }
 }
+
+impl Effect for Compressor {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        self.process_sidechain(frame, frame)
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead
+    }
+
+    fn reset(&mut self) {
+        let settings = self.settings.clone();
+        if let Ok(x) = Self::new(self.fs, self.channels, &settings) {
+            *self = x;
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_compressor_stereo_link() {
+    use crate::generator::Generator;
+
+    // Only the left channel exceeds the threshold by 20 dB.
+    let input = Generator::sequence(48000.0, &[(vec![0.0, -40.0], 1.0)]);
+    let settings = |stereo_indep, link| Settings {
+        stereo_indep,
+        link,
+        ..test_settings()
+    };
+    let gain_db = |settings: &Settings| {
+        response(settings, input.clone(), input.clone())
+            .iter()
+            .map(|x| *x.last().unwrap())
+            .collect::<Vec<f64>>()
+    };
+
+    // Linked: the gain reduction of the left channel applies to both.
+    let gain = gain_db(&settings(false, 100.0));
+    assert!((gain[0] + 15.0).abs() < 0.1);
+    assert!((gain[1] + 15.0).abs() < 0.1);
+
+    // Independent: the quiet right channel is not compressed.
+    for gain in [
+        gain_db(&settings(true, 100.0)),
+        gain_db(&settings(false, 0.0)),
+    ] {
+        assert!((gain[0] + 15.0).abs() < 0.1);
+        assert!(gain[1].abs() < 0.1);
+    }
+
+    // Half linked: half of the gain reduction applies.
+    let gain = gain_db(&settings(false, 50.0));
+    assert!((gain[0] + 15.0).abs() < 0.1);
+    assert!((gain[1] + 7.5).abs() < 0.1);
+
+    assert!(Compressor::new(48000.0, 2, &settings(false, 101.0)).is_err());
+}
+
+#[test]
+fn test_compressor_sidechain() {
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    let input = Generator::sequence(fs, &[(vec![-40.0, -40.0], 1.0)]);
+    let gain_db = |settings: &Settings, frequency| {
+        let signal = Signal::Sine {
+            frequency,
+            phase: 0.0,
+        };
+        let sidechain = Generator::new(&signal, fs, 1, 0.0, 48000).unwrap();
+        *response(settings, input.clone(), sidechain)[0]
+            .last()
+            .unwrap()
+    };
+
+    // The quiet input is compressed by the loud mono sidechain.
+    let mut settings = test_settings();
+    assert!((gain_db(&settings, 1000.0) + 15.0).abs() < 0.1);
+
+    // The high-pass filter removes the low-frequency sidechain signal.
+    settings.sidechain_filter = Some(1000.0);
+    assert!(gain_db(&settings, 20.0).abs() < 0.1);
+}
+
+/// Hard-knee settings with a peak detector for the test signals.
+#[cfg(test)]
+fn test_settings() -> Settings {
+    Settings {
+        detector: PeakDetector::Peak,
+        stereo_indep: false,
+        link: 100.0,
+        threshold_db: -20.0,
+        ratio: 4.0,
+        knee_width_db: 0.0,
+        attack_time: 0.001,
+        release_time: 0.1,
+        lookahead_time: 0.001,
+        hold_time: 0.0,
+        output_gain_db: 0.0,
+        target: Target::Stereo,
+        sidechain: None,
+        sidechain_filter: None,
+    }
+}
+
+/// Gain in dB of every channel and input sample after latency
+/// compensation, the sidechain feeds the detector. The last samples, whose
+/// lookahead reaches beyond the input, are omitted. The lookahead of the
+/// test settings covers a whole period of the 1 kHz test sines, so the
+/// detector sees their peak level.
+#[cfg(test)]
+fn response(
+    settings: &Settings,
+    input: crate::generator::Generator,
+    sidechain: crate::generator::Generator,
+) -> Vec<Vec<f64>> {
+    let channels = input.spec().channels as usize;
+    let mut compressor = Compressor::new(48000.0, channels, settings).unwrap();
+    let latency = compressor.latency();
+    let input: Vec<Vec<f32>> = input.collect();
+    let mut gain = vec![Vec::with_capacity(input.len()); channels];
+    for (i, s) in sidechain.enumerate() {
+        let y = compressor.process_sidechain(&input[i], &s).unwrap();
+        if i >= latency {
+            let x = &input[i - latency];
+            for (channel, (y, x)) in y.iter().zip(x).enumerate() {
+                gain[channel].push(20.0 * (*y as f64 / *x as f64).log10());
+            }
+        }
+    }
+    gain
+}
+
+/// Gain in dB of the first channel, the input feeds the detector.
+#[cfg(test)]
+fn step_response(
+    settings: &Settings,
+    input: crate::generator::Generator,
+) -> Vec<f64> {
+    response(settings, input.clone(), input).swap_remove(0)
+}
+
+#[test]
+fn test_compressor_static_curve() {
+    use crate::generator::Generator;
+
+    let settings = Settings {
+        knee_width_db: 10.0,
+        release_time: 0.01,
+        output_gain_db: 3.0,
+        ..test_settings()
+    };
+    let sine = |level: f64, duration| {
+        Generator::sequence(48000.0, &[(vec![level], duration)])
+    };
+    // (input level, output level) in dB, the knee spans -25 to -15 dB.
+    let curve = [
+        (-40.0, -37.0),
+        (-25.0, -22.0),
+        (-22.0, -19.3375),
+        (-20.0, -17.9375),
+        (-18.0, -16.8375),
+        (-15.0, -15.75),
+        (-10.0, -14.5),
+        (0.0, -12.0),
+    ];
+    for (input, output) in curve {
+        let gain = step_response(&settings, sine(input, 0.2));
+        assert!((input + gain.last().unwrap() - output).abs() < 0.01);
+    }
+
+    // The curve is continuous and monotonic through the knee.
+    let outputs: Vec<f64> = (-300..=-100)
+        .map(|i| {
+            let input = i as f64 / 10.0;
+            input + step_response(&settings, sine(input, 0.05)).last().unwrap()
+        })
+        .collect();
+    for x in outputs.windows(2) {
+        assert!(x[1] > x[0] && x[1] - x[0] < 0.11);
+    }
+}
+
+#[test]
+fn test_compressor_attack_release() {
+    use crate::generator::Generator;
+
+    let fs = 48000.0;
+    let settings = Settings {
+        attack_time: 0.01,
+        release_time: 0.1,
+        ..test_settings()
+    };
+    // The steps fall on a peak of the 1 kHz sine.
+    let input = Generator::sequence(
+        fs,
+        &[(vec![-40.0], 0.50025), (vec![0.0], 1.0), (vec![-40.0], 1.0)],
+    );
+    let gain = step_response(&settings, input);
+    let expected = |env: f64| -0.75 * (20.0 * env.log10() + 20.0).max(0.0);
+    let (step_up, step_down) = (24012, 72012);
+    let lookahead = 48;
+
+    // The envelope covers 1 - 1/e of the step after the time constant.
+    let attack = step_up - lookahead + (0.01 * fs) as usize - 1;
+    let env = 1.0 - 0.99 * (-1.0_f64).exp();
+    assert!(gain[attack - 480].abs() < 0.01);
+    assert!((gain[attack] - expected(env)).abs() < 0.1);
+    assert!((gain[step_down - 1] + 15.0).abs() < 0.01);
+
+    let release = step_down + (0.1 * fs) as usize - 1;
+    let env = 0.01 + 0.99 * (-1.0_f64).exp();
+    assert!((gain[release] - expected(env)).abs() < 0.1);
+    assert!(gain.last().unwrap().abs() < 0.01);
+}
+
+#[test]
+fn test_compressor_lookahead() {
+    use crate::generator::{Generator, Signal};
+
+    let settings = Settings {
+        attack_time: 0.0,
+        release_time: 0.0,
+        lookahead_time: 0.005,
+        ..test_settings()
+    };
+    let lookahead = 240;
+    // The step falls on a peak of the 1 kHz sine.
+    let step = 4812;
+    let input = Generator::sequence(
+        48000.0,
+        &[(vec![-40.0], 0.10025), (vec![0.0], 0.1)],
+    );
+    let gain = step_response(&settings, input);
+
+    // Gain reduction starts exactly one lookahead before the step. The
+    // first sample of the sine is zero.
+    assert!(gain[1..step - lookahead].iter().all(|x| x.abs() < 0.01));
+    assert!(gain[step - lookahead..]
+        .iter()
+        .all(|x| (x + 15.0).abs() < 0.01));
+
+    // Even a single sample peak is caught without hold time.
+    let impulse =
+        Generator::new(&Signal::Impulse, 48000.0, 1, 0.0, 4800).unwrap();
+    let gain = step_response(&settings, impulse);
+    assert!((gain[0] + 15.0).abs() < 0.01);
+
+    // Without lookahead, the input passes without delay.
+    let settings = Settings {
+        lookahead_time: 0.0,
+        ..settings
+    };
+    let mut compressor = Compressor::new(48000.0, 1, &settings).unwrap();
+    assert_eq!(compressor.latency(), 0);
+    for x in Generator::sequence(48000.0, &[(vec![-40.0], 0.1)]) {
+        assert_eq!(compressor.process(&x).unwrap(), x);
+    }
+}
+
+#[test]
+fn test_compressor_latency() {
+    use crate::generator::Generator;
+
+    let input = Generator::sequence(
+        48000.0,
+        &[(vec![-40.0], 0.10025), (vec![0.0], 0.1)],
+    );
+    let spec = hound::WavSpec {
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+        ..input.spec()
+    };
+    let mut output = std::io::Cursor::new(Vec::new());
+    let writer = hound::WavWriter::new(&mut output, spec).unwrap();
+    let mut writer = FrameWriter::new(writer, crate::conversion::Dither::None);
+    let settings = Settings {
+        lookahead_time: 0.005,
+        ..test_settings()
+    };
+    settings
+        .compress(&mut input.clone().into_wav(), &mut writer)
+        .unwrap();
+    writer.finalize().unwrap();
+
+    // The output is aligned with the input and has the same length.
+    output.set_position(0);
+    let output: Vec<f32> = WavReader::new(output)
+        .unwrap()
+        .samples::<f32>()
+        .map(|x| x.unwrap())
+        .collect();
+    let input: Vec<f32> = input.map(|x| x[0]).collect();
+    assert_eq!(output.len(), input.len());
+    assert!((output[12] / input[12] - 1.0).abs() < 0.001);
+    let (x, y) = (input.last().unwrap(), output.last().unwrap());
+    assert!((20.0 * (y / x).log10() + 15.0).abs() < 0.01);
+}
@@ -0,0 +1,111 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::Effect;
+use crate::conversion::FrameWriter;
+use crate::envelope::Envelope;
+use crate::error::Error;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Envelope file with one "position gain_db [curve]" point per line,
+    /// positions in seconds or with unit suffix s, ms or samples
+    filename: String,
+    /// Envelope values are linear gain factors instead of dB
+    #[arg(long)]
+    #[serde(default)]
+    linear: bool,
+}
+
+impl Settings {
+    pub fn build(&self, fs: f64, channels: usize) -> Result<Gain, Error> {
+        Ok(Gain::new(
+            Envelope::load(&self.filename, fs)?,
+            !self.linear,
+            channels,
+        ))
+    }
+
+    pub fn apply<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut gain =
+            self.build(spec.sample_rate as f64, spec.channels as usize)?;
+        super::apply(&mut gain, input, output, "Processing sample")
+    }
+}
+
+/// Time varying gain which follows an envelope
+#[derive(Debug, Clone)]
+pub struct Gain {
+    envelope: Envelope,
+    /// Envelope values are in dB
+    decibel: bool,
+    /// Number of channels
+    channels: usize,
+    /// Current sample index
+    position: usize,
+}
+
+impl Gain {
+    pub fn new(envelope: Envelope, decibel: bool, channels: usize) -> Self {
+        Self {
+            envelope,
+            decibel,
+            channels,
+            position: 0,
+        }
+    }
+}
+
+impl Effect for Gain {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        let value = self.envelope.value(self.position);
+        let gain = if self.decibel {
+            10.0_f64.powf(value / 20.0)
+        } else {
+            value
+        } as f32;
+        self.position += 1;
+
+        Ok(frame.iter().map(|x| x * gain).collect())
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
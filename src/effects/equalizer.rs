@@ -0,0 +1,258 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::Effect;
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use crate::filters::{biquad::Biquad, Filter};
+use clap::ValueEnum;
+use hound::WavReader;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum BandType {
+    /// Low shelf filter
+    LowShelf,
+    /// High shelf filter
+    HighShelf,
+    /// Peaking filter
+    Peaking,
+    /// Low pass filter
+    LowPass,
+    /// High pass filter
+    HighPass,
+    /// Band pass filter
+    BandPass,
+    /// Notch filter
+    Notch,
+    /// All pass filter
+    AllPass,
+}
+
+/// One equalizer band.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Band {
+    /// Filter type
+    band_type: BandType,
+    /// Centre, cutoff or midpoint frequency in Hz
+    freq: f64,
+    /// Filter quality
+    q: f64,
+    /// Filter gain in dB, only used by shelf and peaking filters
+    gain_db: f64,
+}
+
+impl Band {
+    pub fn new(band_type: BandType, freq: f64, q: f64, gain_db: f64) -> Self {
+        Self {
+            band_type,
+            freq,
+            q,
+            gain_db,
+        }
+    }
+
+    /// Constructs the biquad filter of this band.
+    fn filter(&self, fs: f64) -> Result<Biquad, Error> {
+        if self.freq <= 0.0 || self.freq >= fs / 2.0 {
+            return Err(Error::InvalidArgument(format!(
+                "Band frequency {} Hz must be within ]0; {}[ Hz.",
+                self.freq,
+                fs / 2.0
+            )));
+        }
+        if self.q <= 0.0 {
+            return Err(Error::InvalidArgument(
+                "Band Q must be greater than zero.".into(),
+            ));
+        }
+
+        Ok(match self.band_type {
+            BandType::LowShelf => {
+                Biquad::low_shelf(fs, self.freq, self.q, self.gain_db)
+            }
+            BandType::HighShelf => {
+                Biquad::high_shelf(fs, self.freq, self.q, self.gain_db)
+            }
+            BandType::Peaking => {
+                Biquad::peaking(fs, self.freq, self.q, self.gain_db)
+            }
+            BandType::LowPass => Biquad::low_pass(fs, self.freq, self.q),
+            BandType::HighPass => Biquad::high_pass(fs, self.freq, self.q),
+            BandType::BandPass => Biquad::band_pass(fs, self.freq, self.q),
+            BandType::Notch => Biquad::notch(fs, self.freq, self.q),
+            BandType::AllPass => Biquad::all_pass(fs, self.freq, self.q),
+        })
+    }
+}
+
+impl std::str::FromStr for Band {
+    type Err = String;
+
+    /// Parses a band from "type:freq:q[:gain_db]",
+    /// e.g. "peaking:1000:1.4:-3".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(':').collect();
+        if fields.len() < 3 || fields.len() > 4 {
+            return Err("Band must be given as type:freq:q[:gain_db]".into());
+        }
+
+        let band_type = BandType::from_str(fields[0], true)?;
+        let parse = |x: &str| {
+            x.parse::<f64>()
+                .map_err(|e| format!("Invalid band value '{}': {}", x, e))
+        };
+        let gain_db = match fields.get(3) {
+            Some(x) => parse(x)?,
+            None => 0.0,
+        };
+
+        Ok(Self::new(
+            band_type,
+            parse(fields[1])?,
+            parse(fields[2])?,
+            gain_db,
+        ))
+    }
+}
+
+impl TryFrom<String> for Band {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Equalizer bands as type:freq:q[:gain_db], e.g. peaking:1000:1.4:-3
+    #[arg(allow_hyphen_values = true, required = true)]
+    bands: Vec<Band>,
+}
+
+impl Settings {
+    pub fn build(&self, fs: f64, channels: usize) -> Result<Equalizer, Error> {
+        Equalizer::new(fs, channels, &self.bands)
+    }
+
+    pub fn equalize<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut equalizer =
+            self.build(spec.sample_rate as f64, spec.channels as usize)?;
+        super::apply(&mut equalizer, input, output, "Equalizing sample")
+    }
+}
+
+/// Multi-band parametric equalizer
+#[derive(Debug, Clone)]
+pub struct Equalizer {
+    /// Number of channels
+    channels: usize,
+    /// Unmodified filter chain for resetting
+    initial: Vec<Biquad>,
+    /// Chain of band filters for every channel.
+    filter: Vec<Vec<Biquad>>,
+}
+
+impl Equalizer {
+    pub fn new(
+        fs: f64,
+        channels: usize,
+        bands: &[Band],
+    ) -> Result<Self, Error> {
+        let chain = bands
+            .iter()
+            .map(|x| x.filter(fs))
+            .collect::<Result<Vec<Biquad>, Error>>()?;
+
+        Ok(Self {
+            channels,
+            filter: vec![chain.clone(); channels],
+            initial: chain,
+        })
+    }
+}
+
+impl Effect for Equalizer {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        Ok(frame
+            .iter()
+            .zip(self.filter.iter_mut())
+            .map(|(x, chain)| {
+                chain.iter_mut().fold(*x as f64, |acc, f| f.process(acc)) as f32
+            })
+            .collect())
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {
+        self.filter = vec![self.initial.clone(); self.channels];
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_equalizer_centre_gain() {
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    let bands = vec![
+        "peaking:1000:2:6".parse::<Band>().unwrap(),
+        "low-shelf:100:0.7:-6".parse::<Band>().unwrap(),
+    ];
+    let mut equalizer = Equalizer::new(fs, 1, &bands).unwrap();
+
+    // Measure the output amplitude of a settled sine at the peaking filter
+    // centre frequency.
+    let sine = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let mut peak: f64 = 0.0;
+    for (i, x) in Generator::new(&sine, fs, 1, -20.0, 48000)
+        .unwrap()
+        .enumerate()
+    {
+        let y = equalizer.process(&x).unwrap()[0];
+        if i > 24000 {
+            peak = peak.max(y.abs() as f64);
+        }
+    }
+
+    let expected = 0.1 * 10.0_f64.powf(6.0 / 20.0);
+    // The low shelf filter has a small influence at 1 kHz.
+    assert!((20.0 * (peak / expected).log10()).abs() < 0.1);
+}
@@ -0,0 +1,125 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::envelope::Gain;
+use crate::conversion::FrameWriter;
+use crate::envelope::{Breakpoint, Curve, Envelope, Position};
+use crate::error::Error;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// Fade-in length in seconds or with unit suffix s, ms or samples
+    #[arg(long)]
+    fade_in: Option<Position>,
+    /// Fade-out length
+    #[arg(long)]
+    fade_out: Option<Position>,
+    /// Start of the fade-in, the input is muted before
+    #[arg(long, default_value = "0")]
+    fade_in_start: Position,
+    /// End of the fade-out, the input is muted after. Defaults to the end
+    /// of the input.
+    #[arg(long)]
+    fade_out_end: Option<Position>,
+    /// Fade curve
+    #[arg(long, value_enum, default_value_t = Curve::Linear)]
+    curve: Curve,
+}
+
+impl Settings {
+    /// Constructs the fade gain for an input of the given length.
+    pub fn build(
+        &self,
+        fs: f64,
+        channels: usize,
+        length: usize,
+    ) -> Result<Gain, Error> {
+        let point = |position, value| Breakpoint {
+            position,
+            value,
+            curve: self.curve,
+        };
+
+        let mut breakpoints = Vec::new();
+        if let Some(fade_in) = self.fade_in {
+            let start = self.fade_in_start.samples(fs);
+            breakpoints.push(point(start, 0.0));
+            breakpoints.push(point(start + fade_in.samples(fs), 1.0));
+        }
+        if let Some(fade_out) = self.fade_out {
+            let end = self.fade_out_end.map_or(length, |x| x.samples(fs));
+            let fade_out = fade_out.samples(fs);
+            if fade_out > end {
+                return Err(Error::InvalidArgument(
+                    "Fade-out starts before the beginning.".into(),
+                ));
+            }
+            breakpoints.push(point(end - fade_out, 1.0));
+            breakpoints.push(point(end, 0.0));
+        }
+        if breakpoints.is_empty() {
+            return Err(Error::InvalidArgument(
+                "Fade-in or fade-out is required.".into(),
+            ));
+        }
+
+        Envelope::new(breakpoints)
+            .map(|x| Gain::new(x, false, channels))
+            .map_err(|_| {
+                Error::InvalidArgument(
+                    "Fade-in and fade-out must not overlap.".into(),
+                )
+            })
+    }
+
+    pub fn fade<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut gain = self.build(
+            spec.sample_rate as f64,
+            spec.channels as usize,
+            input.duration() as usize,
+        )?;
+        super::apply(&mut gain, input, output, "Fading sample")
+    }
+}
+
+#[test]
+fn test_fade() {
+    use super::Effect;
+
+    let settings = Settings {
+        fade_in: Some("4samples".parse().unwrap()),
+        fade_out: Some("2samples".parse().unwrap()),
+        fade_in_start: "2samples".parse().unwrap(),
+        fade_out_end: None,
+        curve: Curve::Linear,
+    };
+    let mut fade = settings.build(48000.0, 1, 10).unwrap();
+    let output: Vec<f32> =
+        (0..10).map(|_| fade.process(&[1.0]).unwrap()[0]).collect();
+    assert_eq!(output, [0.0, 0.0, 0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 0.5]);
+    assert!(settings.build(48000.0, 1, 5).is_err());
+}
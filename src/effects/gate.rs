@@ -0,0 +1,284 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::compressor::PeakDetector;
+use super::Effect;
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use crate::filters::{lag1::Lag1, mov_max::MovMax, mov_rms::MovRms, Filter};
+use hound::WavReader;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+#[command(allow_negative_numbers = true)]
+pub struct Settings {
+    /// Peak detector to use
+    detector: PeakDetector,
+    /// Gate threshold in dB
+    threshold_db: f64,
+    /// Expansion ratio. Must be at least one, use a large value for a gate.
+    ratio: f64,
+    /// Maximum attenuation in dB.
+    range_db: f64,
+    /// Gate attack (opening) time in seconds.
+    attack_time: f64,
+    /// Gate hold time in seconds.
+    hold_time: f64,
+    /// Gate release (closing) time in seconds.
+    release_time: f64,
+    /// Gate lookahead time in seconds.
+    lookahead_time: f64,
+    /// Gate hysteresis in dB. The gate closes if the level falls this
+    /// amount below the threshold.
+    hysteresis_db: f64,
+}
+
+impl Settings {
+    pub fn gate<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut gate =
+            Gate::new(spec.sample_rate as f64, spec.channels as usize, self)?;
+        super::apply(&mut gate, input, output, "Gating sample")
+    }
+}
+
+/// Noise gate and downward expander
+#[derive(Debug)]
+pub struct Gate {
+    /// Sampling rate
+    fs: f64,
+    /// Gate settings for resetting
+    settings: Settings,
+    /// Number of channels
+    channels: usize,
+    /// Envelope detector preprocessing filter
+    preprocessor: Box<dyn Filter>,
+    /// Lookahead in samples, this is also the filter latency.
+    lookahead: usize,
+    /// Gain smoothing filter.
+    envelope: Lag1,
+    /// Filter input data buffer.
+    buffer: VecDeque<Vec<f32>>,
+    /// Gate threshold in dB.
+    threshold_db: f64,
+    /// Expansion ratio.
+    ratio: f64,
+    /// Maximum attenuation in dB.
+    range_db: f64,
+    /// Hysteresis in dB.
+    hysteresis_db: f64,
+    /// Hold time in samples.
+    hold: usize,
+    /// Remaining hold samples until the gate closes.
+    hold_counter: usize,
+    /// Gate state
+    open: bool,
+}
+
+impl Gate {
+    pub fn new(
+        fs: f64,
+        channels: usize,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        if settings.ratio < 1.0 {
+            return Err(Error::InvalidArgument(
+                "Ratio must be at least one.".into(),
+            ));
+        }
+        if settings.range_db < 0.0 || settings.hysteresis_db < 0.0 {
+            return Err(Error::InvalidArgument(
+                "Range and hysteresis must not be negative.".into(),
+            ));
+        }
+        let times = [
+            settings.attack_time,
+            settings.release_time,
+            settings.lookahead_time,
+            settings.hold_time,
+        ];
+        if !times.iter().all(|x| *x >= 0.0) {
+            return Err(Error::InvalidArgument(
+                "Attack, release, lookahead and hold time must not be \
+                 negative."
+                    .into(),
+            ));
+        }
+
+        let lookahead = (settings.lookahead_time * fs) as usize;
+        let window = lookahead.max(1) * channels;
+        let preprocessor = match settings.detector {
+            PeakDetector::Peak => {
+                Box::new(MovMax::new(window)) as Box<dyn Filter>
+            }
+            // Scale RMS to the peak value of a sine.
+            PeakDetector::Rms => {
+                Box::new(MovRms::new(2.0_f64.sqrt(), window)) as Box<dyn Filter>
+            }
+        };
+
+        // The gate starts closed, begin with the fully attenuated gain.
+        let mut envelope =
+            Lag1::new(1.0, settings.attack_time, settings.release_time, fs);
+        envelope.reset(10.0_f64.powf(-settings.range_db / 20.0));
+
+        Ok(Self {
+            fs,
+            settings: settings.clone(),
+            channels,
+            preprocessor,
+            lookahead,
+            envelope,
+            buffer: VecDeque::from(vec![vec![0.0; channels]; lookahead]),
+            threshold_db: settings.threshold_db,
+            ratio: settings.ratio,
+            range_db: settings.range_db,
+            hysteresis_db: settings.hysteresis_db,
+            hold: (settings.hold_time * fs) as usize,
+            hold_counter: 0,
+            open: false,
+        })
+    }
+
+    /// Calculates the target gain from the detected level and updates
+    /// the gate state.
+    fn gain(&mut self, level: f64) -> f64 {
+        let level_db = 20.0 * level.log10();
+        // Prevent NaN propagation with a very low dB value if level is zero.
+        let level_db = if level_db.is_nan() { -200.0 } else { level_db };
+
+        if level_db >= self.threshold_db {
+            self.open = true;
+            self.hold_counter = self.hold;
+        } else if level_db < self.threshold_db - self.hysteresis_db {
+            if self.hold_counter > 0 {
+                self.hold_counter -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+
+        if self.open {
+            1.0
+        } else {
+            // Below threshold: expand level difference to threshold.
+            let gain_db = ((level_db - self.threshold_db) * (self.ratio - 1.0))
+                .max(-self.range_db);
+            10.0_f64.powf(gain_db / 20.0)
+        }
+    }
+}
+
+impl Effect for Gate {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        let mut preproc = 0.0;
+        for x in frame {
+            preproc = self.preprocessor.process(*x as f64);
+        }
+
+        let target = self.gain(preproc);
+        let gain = self.envelope.process(target) as f32;
+        let current_frame = if self.lookahead == 0 {
+            frame.to_owned()
+        } else {
+            let current_frame = self.buffer.pop_front().unwrap();
+            self.buffer.push_back(frame.to_owned());
+            current_frame
+        };
+
+        Ok(current_frame.iter().map(|x| x * gain).collect())
+    }
+
+    fn latency(&self) -> usize {
+        self.lookahead
+    }
+
+    fn reset(&mut self) {
+        let settings = self.settings.clone();
+        // Settings were already validated on construction.
+        if let Ok(gate) = Self::new(self.fs, self.channels, &settings) {
+            *self = gate;
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_gate_attenuates_quiet_section() {
+    use crate::generator::Generator;
+
+    let fs = 48000.0;
+    let settings = Settings {
+        detector: PeakDetector::Peak,
+        threshold_db: -30.0,
+        ratio: 100.0,
+        range_db: 40.0,
+        attack_time: 0.001,
+        hold_time: 0.05,
+        release_time: 0.01,
+        lookahead_time: 0.002,
+        hysteresis_db: 3.0,
+    };
+    let mut gate = Gate::new(fs, 1, &settings).unwrap();
+
+    // One second of loud tone followed by one second of quiet noise floor.
+    let input: Vec<f32> =
+        Generator::sequence(fs, &[(vec![-6.0], 1.0), (vec![-60.0], 1.0)])
+            .map(|x| x[0])
+            .collect();
+    let latency = gate.latency();
+    let output: Vec<f32> = input
+        .iter()
+        .chain(std::iter::repeat(&0.0).take(latency))
+        .map(|x| gate.process(&[*x]).unwrap()[0])
+        .skip(latency)
+        .collect();
+
+    // Loud section passes unchanged after the gate opened.
+    for i in 4800..48000 {
+        assert!((output[i] - input[i]).abs() < 1e-6);
+    }
+    // Quiet section is attenuated by the range after hold and release.
+    for i in 60000..96000 {
+        assert!((output[i] - 0.01 * input[i]).abs() < 1e-6);
+    }
+
+    // A quiet input is attenuated by the range right from the first sample.
+    gate.reset();
+    for i in 0..latency {
+        assert_eq!(gate.process(&[input[48000 + i]]).unwrap()[0], 0.0);
+    }
+    for i in latency..4800 {
+        let y = gate.process(&[input[48000 + i]]).unwrap()[0];
+        assert!((y - 0.01 * input[48000 + i - latency]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,261 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::Effect;
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use crate::filters::{
+    fir::Fir, lag1::Lag1, mov_avg::MovAvg, mov_max::MovMax, Filter,
+};
+use hound::WavReader;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+#[command(allow_negative_numbers = true)]
+pub struct Settings {
+    /// Limiter true peak ceiling in dBTP
+    ceiling_db: f64,
+    /// Limiter lookahead time in seconds.
+    lookahead_time: f64,
+    /// Limiter release time in seconds.
+    release_time: f64,
+}
+
+impl Settings {
+    pub fn new(
+        ceiling_db: f64,
+        lookahead_time: f64,
+        release_time: f64,
+    ) -> Self {
+        Self {
+            ceiling_db,
+            lookahead_time,
+            release_time,
+        }
+    }
+
+    pub fn limit<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut limiter = Limiter::new(
+            spec.sample_rate as f64,
+            spec.channels as usize,
+            self,
+        )?;
+        super::apply(&mut limiter, input, output, "Limiting sample")
+    }
+}
+
+/// Lookahead true peak brickwall limiter
+#[derive(Debug)]
+pub struct Limiter {
+    /// Sampling rate
+    fs: f64,
+    /// Limiter settings for resetting
+    settings: Settings,
+    /// Number of channels
+    channels: usize,
+    /// True peak ceiling in linear units
+    ceiling: f64,
+    /// Upsampling filters of the true peak detector
+    upsampler: Vec<Fir>,
+    /// Peak hold filter, covers the lookahead and detector span
+    hold: MovMax,
+    /// Gain smoothing filter over the lookahead time
+    smoothing: MovAvg,
+    /// Gain release filter
+    release: Lag1,
+    /// Total latency in samples
+    latency: usize,
+    /// Filter input data buffer.
+    buffer: VecDeque<Vec<f32>>,
+}
+
+impl Limiter {
+    /// True peak detector upsampling factor
+    const OVERSAMPLING: usize = 4;
+    /// Number of samples before the current input sample which may
+    /// contribute to a detected true peak. This is the upsampling filter
+    /// delay plus the Lanczos kernel half-width.
+    const DETECTOR_PRE: usize = 6;
+    /// Number of samples after the current input sample which may
+    /// contribute to a detected true peak.
+    const DETECTOR_POST: usize = 2;
+
+    pub fn new(
+        fs: f64,
+        channels: usize,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        if !settings.ceiling_db.is_finite() || settings.ceiling_db > 0.0 {
+            return Err(Error::InvalidArgument(
+                "Ceiling must not be above 0 dBTP.".into(),
+            ));
+        }
+        if !(settings.lookahead_time >= 0.0 && settings.release_time >= 0.0) {
+            return Err(Error::InvalidArgument(
+                "Lookahead and release time must not be negative.".into(),
+            ));
+        }
+
+        let lookahead = ((settings.lookahead_time * fs) as usize).max(1);
+        let latency = lookahead - 1 + Self::DETECTOR_PRE;
+        // Gain reduction is applied instantly after smoothing,
+        // only the recovery is slowed down.
+        let mut release = Lag1::new(1.0, settings.release_time, 0.0, fs);
+        release.reset(1.0);
+
+        Ok(Self {
+            fs,
+            settings: settings.clone(),
+            channels,
+            ceiling: 10.0_f64.powf(settings.ceiling_db / 20.0),
+            upsampler: vec![Fir::lanczos(Self::OVERSAMPLING, 3); channels],
+            hold: MovMax::new(
+                lookahead + Self::DETECTOR_PRE + Self::DETECTOR_POST,
+            ),
+            smoothing: MovAvg::new(lookahead, 1.0),
+            release,
+            latency,
+            buffer: VecDeque::from(vec![vec![0.0; channels]; latency]),
+        })
+    }
+}
+
+impl Effect for Limiter {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        // Detect true peak of all channels with upsampling.
+        let mut peak: f64 = 0.0;
+        for (i, x) in frame.iter().enumerate() {
+            peak = peak.max(self.upsampler[i].process(*x as f64).abs());
+            for _ in 1..Self::OVERSAMPLING {
+                peak = peak.max(self.upsampler[i].process(0.0).abs());
+            }
+        }
+
+        // Hold the peak over the lookahead window and smooth the required
+        // gain so that it reaches the target gain exactly when the peak
+        // leaves the delay buffer.
+        let peak = self.hold.process(peak);
+        let target = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+        let gain = self.smoothing.process(target);
+        let gain = self.release.process(gain) as f32;
+
+        let current_frame = self.buffer.pop_front().unwrap();
+        self.buffer.push_back(frame.to_owned());
+
+        Ok(current_frame.iter().map(|x| x * gain).collect())
+    }
+
+    fn latency(&self) -> usize {
+        self.latency
+    }
+
+    fn reset(&mut self) {
+        let settings = self.settings.clone();
+        // Settings were already validated on construction.
+        if let Ok(limiter) = Self::new(self.fs, self.channels, &settings) {
+            *self = limiter;
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_limiter_true_peak_ceiling() {
+    use crate::analyzer::true_peak::TruePeak;
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    let settings = Settings::new(-1.0, 0.005, 0.05);
+    let mut limiter = Limiter::new(fs, 2, &settings).unwrap();
+    let mut analyzer = TruePeak::new(2);
+
+    // 11 kHz sine whose peaks mostly fall between the samples, alternating
+    // between -20 and 0 dBFS every 100 ms.
+    let signal = Signal::Sine {
+        frequency: 11000.0,
+        phase: 0.0,
+    };
+    let frames = (0..10)
+        .flat_map(|i| {
+            let level = if i % 2 == 0 { -20.0 } else { 0.0 };
+            Generator::new(&signal, fs, 2, level, 4800).unwrap()
+        })
+        .chain(std::iter::repeat(vec![0.0, 0.0]).take(limiter.latency()));
+
+    for (i, frame) in frames.enumerate() {
+        let output = limiter.process(&frame).unwrap();
+        if i >= limiter.latency() {
+            analyzer.process(&output).unwrap();
+        }
+    }
+
+    let ceiling = 10.0_f64.powf(-1.0 / 20.0);
+    assert!(analyzer.true_peak() <= ceiling);
+    assert!(analyzer.true_peak() > 0.95 * ceiling);
+}
+
+#[test]
+fn test_limiter_unity_gain() {
+    use crate::generator::{Generator, Signal};
+
+    // Signals below the ceiling pass unchanged right from the first sample.
+    let fs = 48000.0;
+    let settings = Settings::new(-1.0, 0.005, 0.05);
+    let mut limiter = Limiter::new(fs, 2, &settings).unwrap();
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let input: Vec<Vec<f32>> = Generator::new(&signal, fs, 2, -3.0, 4800)
+        .unwrap()
+        .collect();
+
+    let latency = limiter.latency();
+    for (i, frame) in input
+        .iter()
+        .cloned()
+        .chain(std::iter::repeat(vec![0.0, 0.0]).take(latency))
+        .enumerate()
+    {
+        let output = limiter.process(&frame).unwrap();
+        if i >= latency {
+            assert_eq!(output, input[i - latency]);
+        } else {
+            assert_eq!(output, [0.0, 0.0]);
+        }
+    }
+}
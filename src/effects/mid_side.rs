@@ -0,0 +1,277 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::Effect;
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use hound::WavReader;
+use std::collections::VecDeque;
+
+/// Signal which an effect is applied to.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Target {
+    /// All input channels
+    Stereo,
+    /// Mid signal of stereo input only
+    Mid,
+    /// Side signal of stereo input only
+    Side,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::Stereo
+    }
+}
+
+impl Target {
+    /// Number of channels the targeted effect has to process.
+    pub fn channels(&self, channels: usize) -> Result<usize, Error> {
+        match self {
+            Self::Stereo => Ok(channels),
+            Self::Mid | Self::Side => {
+                require_stereo(channels)?;
+                Ok(1)
+            }
+        }
+    }
+
+    /// Returns the targeted part of the frame.
+    pub fn select(&self, frame: &[f32]) -> Vec<f32> {
+        match self {
+            Self::Stereo => frame.to_vec(),
+            Self::Mid => vec![encode(frame).0],
+            Self::Side => vec![encode(frame).1],
+        }
+    }
+
+    /// Wraps an effect which was constructed for
+    /// [`Target::channels`] channels so that it processes stereo frames.
+    pub fn wrap(&self, effect: Box<dyn Effect>) -> Box<dyn Effect> {
+        match self {
+            Self::Stereo => effect,
+            Self::Mid | Self::Side => Box::new(Targeted::new(*self, effect)),
+        }
+    }
+}
+
+/// Returns an error if the number of channels is not two.
+pub fn require_stereo(channels: usize) -> Result<(), Error> {
+    if channels != 2 {
+        return Err(Error::InvalidArgument(format!(
+            "Mid/side processing requires stereo input, got {} channels.",
+            channels
+        )));
+    }
+
+    Ok(())
+}
+
+/// Converts left and right to mid and side.
+pub fn encode(frame: &[f32]) -> (f32, f32) {
+    (0.5 * (frame[0] + frame[1]), 0.5 * (frame[0] - frame[1]))
+}
+
+/// Converts mid and side to left and right.
+pub fn decode(mid: f32, side: f32) -> Vec<f32> {
+    vec![mid + side, mid - side]
+}
+
+#[derive(Debug, Clone, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    /// Convert left and right to mid and side
+    Encode,
+    /// Convert mid and side to left and right
+    Decode,
+}
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Conversion direction
+    direction: Direction,
+}
+
+impl Settings {
+    pub fn build(&self, channels: usize) -> Result<MidSide, Error> {
+        require_stereo(channels)?;
+        Ok(MidSide {
+            direction: self.direction.clone(),
+        })
+    }
+
+    pub fn convert<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut mid_side = self.build(input.spec().channels as usize)?;
+        super::apply(&mut mid_side, input, output, "Converting sample")
+    }
+}
+
+/// Mid/side encoder or decoder
+#[derive(Debug, Clone)]
+pub struct MidSide {
+    direction: Direction,
+}
+
+impl Effect for MidSide {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != 2 {
+            return Err(Error::InvalidFrame);
+        }
+
+        Ok(match self.direction {
+            Direction::Encode => {
+                let (mid, side) = encode(frame);
+                vec![mid, side]
+            }
+            Direction::Decode => decode(frame[0], frame[1]),
+        })
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {}
+
+    fn output_channels(&self) -> usize {
+        2
+    }
+}
+
+/// Applies a mono effect to the mid or side signal of stereo frames.
+/// The other signal is delayed by the effect latency. The stereo target
+/// passes all channels to the effect.
+#[derive(Debug)]
+pub struct Targeted<E: ?Sized = dyn Effect> {
+    target: Target,
+    effect: Box<E>,
+    /// Delay line of the signal which is not processed
+    buffer: VecDeque<f32>,
+}
+
+impl<E> Targeted<E>
+where
+    E: Effect + ?Sized,
+{
+    pub fn new(target: Target, effect: Box<E>) -> Self {
+        let buffer = VecDeque::from(vec![0.0; effect.latency()]);
+        Self {
+            target,
+            effect,
+            buffer,
+        }
+    }
+
+    /// Processes the targeted part of the frame with the given function
+    /// of the effect.
+    pub fn process_with<F>(
+        &mut self,
+        frame: &[f32],
+        f: F,
+    ) -> Result<Vec<f32>, Error>
+    where
+        F: FnOnce(&mut E, &[f32]) -> Result<Vec<f32>, Error>,
+    {
+        if self.target == Target::Stereo {
+            return f(&mut self.effect, frame);
+        }
+        if frame.len() != 2 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let (mid, side) = encode(frame);
+        let (x, other) = match self.target {
+            Target::Side => (side, mid),
+            _ => (mid, side),
+        };
+        let y = f(&mut self.effect, &[x])?[0];
+        self.buffer.push_back(other);
+        let other = self.buffer.pop_front().unwrap();
+
+        Ok(match self.target {
+            Target::Side => decode(other, y),
+            _ => decode(y, other),
+        })
+    }
+}
+
+impl<E> Effect for Targeted<E>
+where
+    E: Effect + ?Sized,
+{
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        self.process_with(frame, |effect, x| effect.process(x))
+    }
+
+    fn latency(&self) -> usize {
+        self.effect.latency()
+    }
+
+    fn reset(&mut self) {
+        self.effect.reset();
+        self.buffer = VecDeque::from(vec![0.0; self.effect.latency()]);
+    }
+
+    fn output_channels(&self) -> usize {
+        match self.target {
+            Target::Stereo => self.effect.output_channels(),
+            _ => 2,
+        }
+    }
+}
+
+#[test]
+fn test_mid_side() {
+    let frame = [0.5, -0.25];
+    let mut encoder = Settings {
+        direction: Direction::Encode,
+    }
+    .build(2)
+    .unwrap();
+    let mut decoder = Settings {
+        direction: Direction::Decode,
+    }
+    .build(2)
+    .unwrap();
+    let encoded = encoder.process(&frame).unwrap();
+    assert_eq!(encoded, [0.125, 0.375]);
+    assert_eq!(decoder.process(&encoded).unwrap(), frame);
+    assert!(matches!(
+        Target::Mid.channels(3),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    // Muting the side signal yields mono output.
+    let amplifier = super::amplify::Settings::new(vec![-200.0])
+        .build(1)
+        .unwrap();
+    let mut side = Targeted::new(Target::Side, amplifier);
+    let output = side.process(&frame).unwrap();
+    assert!((output[0] - 0.125).abs() < 1e-6);
+    assert!((output[1] - 0.125).abs() < 1e-6);
+}
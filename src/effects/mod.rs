@@ -16,4 +16,119 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 pub mod amplify;
+pub mod chain;
 pub mod compressor;
+pub mod envelope;
+pub mod equalizer;
+pub mod fade;
+pub mod gate;
+pub mod limiter;
+pub mod mid_side;
+pub mod multiband;
+pub mod width;
+
+use crate::conversion::{Conversion, FrameWriter};
+use crate::error::Error;
+use crate::frame::FrameIterator;
+use crate::progress::Progress;
+use hound::WavReader;
+
+/// Streaming audio effect which processes one frame at a time.
+pub trait Effect: std::fmt::Debug {
+    /// Processes one frame and returns the output frame which is delayed
+    /// by the effect latency.
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error>;
+
+    /// Processes a block of frames.
+    fn process_block(
+        &mut self,
+        block: &[Vec<f32>],
+    ) -> Result<Vec<Vec<f32>>, Error> {
+        block.iter().map(|x| self.process(x)).collect()
+    }
+
+    /// Processing latency in frames.
+    fn latency(&self) -> usize;
+
+    /// Resets the effect to its initial state.
+    fn reset(&mut self);
+
+    /// Number of channels in the output frames.
+    fn output_channels(&self) -> usize;
+}
+
+/// Applies the effect to all input frames and compensates its latency.
+/// The effect is reset afterwards so that it can be applied to
+/// another input.
+pub fn apply<R, W>(
+    effect: &mut dyn Effect,
+    input: &mut WavReader<R>,
+    output: &mut FrameWriter<W>,
+    message: &str,
+) -> Result<(), Error>
+where
+    R: std::io::Read,
+    W: std::io::Write + std::io::Seek,
+{
+    apply_to(
+        effect,
+        input,
+        |frame| Ok(output.write_frame(frame)?),
+        message,
+    )
+}
+
+/// Applies the effect to all input frames and passes the latency
+/// compensated output frames to the sink.
+pub fn apply_to<R, F>(
+    effect: &mut dyn Effect,
+    input: &mut WavReader<R>,
+    mut sink: F,
+    message: &str,
+) -> Result<(), Error>
+where
+    R: std::io::Read,
+    F: FnMut(&[f32]) -> Result<(), Error>,
+{
+    const BLOCK_SIZE: usize = 1024;
+    let spec = input.spec();
+    let duration = input.duration();
+
+    let mut skip = effect.latency();
+    let mut write_block = |block: Vec<Vec<f32>>| -> Result<(), Error> {
+        for frame in block {
+            // Compensate filter latency
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            sink(&frame)?;
+        }
+        Ok(())
+    };
+
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    let mut progress = Progress::new(duration as usize, message);
+    let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+    while let Some(frame) = frames.next() {
+        progress.next();
+        match frame {
+            Ok(frame) => {
+                block.push(frame.to_owned());
+                if block.len() == BLOCK_SIZE {
+                    write_block(effect.process_block(&block)?)?;
+                    block.clear();
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // Drain processing pipeline
+    let padding = vec![0.0; spec.channels as usize];
+    block.extend(std::iter::repeat(padding).take(effect.latency()));
+    write_block(effect.process_block(&block)?)?;
+    effect.reset();
+
+    Ok(())
+}
@@ -0,0 +1,410 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{compressor, Effect};
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use crate::filters::{biquad::Biquad, fir::Fir, Filter};
+use clap::Parser;
+use hound::WavReader;
+use std::collections::VecDeque;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+/// Crossover filter type
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Crossover {
+    /// 4th order Linkwitz-Riley crossovers
+    LinkwitzRiley,
+    /// Linear-phase FIR crossovers with higher latency
+    LinearPhase,
+}
+
+impl Default for Crossover {
+    fn default() -> Self {
+        Self::LinkwitzRiley
+    }
+}
+
+/// Processing of one frequency band.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Band {
+    /// Pass the band unchanged
+    Bypass,
+    /// Compress the band
+    Compressor(compressor::Settings),
+}
+
+/// Command line parser for the compressor settings of a band.
+#[derive(Debug, Parser)]
+#[command(name = "band", allow_negative_numbers = true)]
+struct BandParser {
+    #[command(flatten)]
+    settings: compressor::Settings,
+}
+
+impl std::str::FromStr for Band {
+    type Err = String;
+
+    /// Parses a band from "bypass" or the arguments of the compressor
+    /// command, e.g. "peak -20 4 0 0.01 0.1 0 0 0".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "bypass" {
+            return Ok(Self::Bypass);
+        }
+
+        BandParser::try_parse_from(
+            std::iter::once("band").chain(s.split_whitespace()),
+        )
+        .map(|x| Self::Compressor(x.settings))
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl TryFrom<String> for Band {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Crossover filter type
+    #[arg(long, value_enum, default_value_t = Crossover::LinkwitzRiley)]
+    #[serde(default)]
+    crossover: Crossover,
+    /// Ascending crossover frequencies in Hz, e.g. 200,2000
+    #[arg(long, required = true, value_delimiter = ',')]
+    frequencies: Vec<f64>,
+    /// Band from low to high frequencies, one more than crossover
+    /// frequencies. Either "bypass" or the compressor command arguments,
+    /// e.g. "peak -20 4 0 0.01 0.1 0 0 0"
+    #[arg(long = "band", required = true, allow_hyphen_values = true)]
+    bands: Vec<Band>,
+}
+
+impl Settings {
+    pub fn build(&self, fs: f64, channels: usize) -> Result<Multiband, Error> {
+        Multiband::new(fs, channels, self)
+    }
+
+    pub fn compress<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let mut multiband =
+            self.build(spec.sample_rate as f64, spec.channels as usize)?;
+        super::apply(&mut multiband, input, output, "Compressing sample")
+    }
+}
+
+/// Band splitting filter bank of one channel.
+#[derive(Debug, Clone)]
+enum Splitter {
+    /// Tree of Linkwitz-Riley crossovers. The low-pass filters of every
+    /// crossover are followed by the all-pass filters of the higher
+    /// crossovers so that all bands have the same phase response.
+    LinkwitzRiley {
+        low_pass: Vec<Vec<Biquad>>,
+        high_pass: Vec<Vec<Biquad>>,
+    },
+    /// Low-pass filters of all crossovers. The bands are the differences
+    /// of adjacent low-pass outputs and the delayed input.
+    LinearPhase {
+        low_pass: Vec<Fir>,
+        delay: VecDeque<f64>,
+    },
+}
+
+impl Splitter {
+    fn new(crossover: Crossover, fs: f64, frequencies: &[f64]) -> Self {
+        match crossover {
+            Crossover::LinkwitzRiley => {
+                let low_pass = (0..frequencies.len())
+                    .map(|i| {
+                        let f0 = frequencies[i];
+                        let mut filters =
+                            vec![Biquad::low_pass(fs, f0, FRAC_1_SQRT_2); 2];
+                        filters.extend(frequencies[i + 1..].iter().map(|f0| {
+                            Biquad::all_pass(fs, *f0, FRAC_1_SQRT_2)
+                        }));
+                        filters
+                    })
+                    .collect();
+                let high_pass = frequencies
+                    .iter()
+                    .map(|f0| {
+                        vec![Biquad::high_pass(fs, *f0, FRAC_1_SQRT_2); 2]
+                    })
+                    .collect();
+                Self::LinkwitzRiley {
+                    low_pass,
+                    high_pass,
+                }
+            }
+            Crossover::LinearPhase => {
+                let taps = Self::taps(fs, frequencies);
+                Self::LinearPhase {
+                    low_pass: frequencies
+                        .iter()
+                        .map(|f0| Fir::low_pass(fs, *f0, taps))
+                        .collect(),
+                    delay: VecDeque::from(vec![0.0; taps / 2]),
+                }
+            }
+        }
+    }
+
+    /// Number of FIR taps, the transition width of the Blackman window
+    /// equals the lowest crossover frequency.
+    fn taps(fs: f64, frequencies: &[f64]) -> usize {
+        (5.5 * fs / frequencies[0]) as usize | 1
+    }
+
+    fn latency(&self) -> usize {
+        match self {
+            Self::LinkwitzRiley { .. } => 0,
+            Self::LinearPhase { delay, .. } => delay.len(),
+        }
+    }
+
+    /// Splits one sample into the bands from low to high frequencies.
+    fn split(&mut self, x: f64) -> Vec<f64> {
+        match self {
+            Self::LinkwitzRiley {
+                low_pass,
+                high_pass,
+            } => {
+                let cascade = |filters: &mut Vec<Biquad>, x: f64| {
+                    filters.iter_mut().fold(x, |x, f| f.process(x))
+                };
+                let mut bands = Vec::with_capacity(low_pass.len() + 1);
+                let mut high = x;
+                for (lp, hp) in low_pass.iter_mut().zip(high_pass.iter_mut()) {
+                    bands.push(cascade(lp, high));
+                    high = cascade(hp, high);
+                }
+                bands.push(high);
+                bands
+            }
+            Self::LinearPhase { low_pass, delay } => {
+                let mut bands = Vec::with_capacity(low_pass.len() + 1);
+                let mut previous = 0.0;
+                for filter in low_pass.iter_mut() {
+                    let low = filter.process(x);
+                    bands.push(low - previous);
+                    previous = low;
+                }
+                delay.push_back(x);
+                bands.push(delay.pop_front().unwrap() - previous);
+                bands
+            }
+        }
+    }
+}
+
+/// Multiband compressor
+#[derive(Debug)]
+pub struct Multiband {
+    /// Sampling rate
+    fs: f64,
+    /// Settings for resetting
+    settings: Settings,
+    /// Number of channels
+    channels: usize,
+    /// Band splitting filters of each channel
+    splitters: Vec<Splitter>,
+    /// Compressor of each band, None if bypassed
+    compressors: Vec<Option<Box<dyn Effect>>>,
+    /// Delay lines which match the latency of all bands
+    delays: Vec<VecDeque<Vec<f32>>>,
+    /// Total latency in samples
+    latency: usize,
+}
+
+impl Multiband {
+    pub fn new(
+        fs: f64,
+        channels: usize,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        let frequencies = &settings.frequencies;
+        if frequencies.is_empty() {
+            return Err(Error::InvalidArgument(
+                "At least one crossover frequency is required.".into(),
+            ));
+        }
+        if frequencies.windows(2).any(|x| x[0] >= x[1])
+            || frequencies[0] <= 0.0
+            || frequencies[frequencies.len() - 1] >= fs / 2.0
+        {
+            return Err(Error::InvalidArgument(
+                "Crossover frequencies must be ascending and between zero \
+                 and half the sampling rate."
+                    .into(),
+            ));
+        }
+        if settings.bands.len() != frequencies.len() + 1 {
+            return Err(Error::InvalidArgument(format!(
+                "{} crossover frequencies require {} bands.",
+                frequencies.len(),
+                frequencies.len() + 1
+            )));
+        }
+
+        let compressors = settings
+            .bands
+            .iter()
+            .map(|band| match band {
+                Band::Bypass => Ok(None),
+                Band::Compressor(x) => x.build(fs, channels).map(Some),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let band_latency =
+            |x: &Option<Box<dyn Effect>>| x.as_ref().map_or(0, |x| x.latency());
+        let max_latency =
+            compressors.iter().map(band_latency).max().unwrap_or(0);
+        let delays = compressors
+            .iter()
+            .map(|x| {
+                VecDeque::from(vec![
+                    vec![0.0; channels];
+                    max_latency - band_latency(x)
+                ])
+            })
+            .collect();
+        let splitter = Splitter::new(settings.crossover, fs, frequencies);
+
+        Ok(Self {
+            fs,
+            settings: settings.clone(),
+            channels,
+            latency: splitter.latency() + max_latency,
+            splitters: vec![splitter; channels],
+            compressors,
+            delays,
+        })
+    }
+}
+
+impl Effect for Multiband {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        let split: Vec<Vec<f64>> = frame
+            .iter()
+            .zip(self.splitters.iter_mut())
+            .map(|(x, splitter)| splitter.split(*x as f64))
+            .collect();
+
+        let mut output = vec![0.0; self.channels];
+        for (band, (compressor, delay)) in self
+            .compressors
+            .iter_mut()
+            .zip(self.delays.iter_mut())
+            .enumerate()
+        {
+            let band_frame: Vec<f32> =
+                split.iter().map(|x| x[band] as f32).collect();
+            let band_frame = match compressor {
+                Some(compressor) => compressor.process(&band_frame)?,
+                None => band_frame,
+            };
+            delay.push_back(band_frame);
+            let band_frame = delay.pop_front().unwrap();
+            for (y, x) in output.iter_mut().zip(band_frame.iter()) {
+                *y += x;
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn latency(&self) -> usize {
+        self.latency
+    }
+
+    fn reset(&mut self) {
+        let settings = self.settings.clone();
+        if let Ok(x) = Self::new(self.fs, self.channels, &settings) {
+            *self = x;
+        }
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+}
+
+#[test]
+fn test_multiband_flat_sum() {
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    for crossover in [Crossover::LinkwitzRiley, Crossover::LinearPhase] {
+        let mut multiband = Settings {
+            crossover,
+            frequencies: vec![200.0, 2000.0],
+            bands: vec![Band::Bypass, Band::Bypass, Band::Bypass],
+        }
+        .build(fs, 1)
+        .unwrap();
+        let latency = multiband.latency();
+
+        // Without compression the bands sum to an all-pass response.
+        for frequency in [50.0, 200.0, 700.0, 2000.0, 10000.0] {
+            let n = 48000;
+            let sine = Signal::Sine {
+                frequency,
+                phase: 0.0,
+            };
+            let output: Vec<f32> = Generator::new(&sine, fs, 1, 0.0, n)
+                .unwrap()
+                .map(|x| multiband.process(&x).unwrap()[0])
+                .collect();
+            let peak = output[n / 2..].iter().fold(0.0_f32, |a, x| a.max(*x));
+            assert!((20.0 * peak.log10()).abs() < 0.01, "{} Hz", frequency);
+            multiband.reset();
+        }
+
+        // The linear-phase bands sum to the delayed input.
+        if crossover == Crossover::LinearPhase {
+            let input: Vec<f32> =
+                (0..2 * latency).map(|i| ((i * 7919) % 13) as f32).collect();
+            let output: Vec<f32> = input
+                .iter()
+                .map(|x| multiband.process(&[*x]).unwrap()[0])
+                .collect();
+            for (y, x) in output[latency..].iter().zip(input.iter()) {
+                assert!((y - x).abs() < 1e-3);
+            }
+        }
+    }
+}
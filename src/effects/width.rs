@@ -0,0 +1,101 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::{mid_side, Effect};
+use crate::conversion::FrameWriter;
+use crate::error::Error;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+pub struct Settings {
+    /// Side signal gain factor, 0 is mono, 1 keeps the input unchanged
+    /// and values above 1 widen the stereo image.
+    width: f32,
+}
+
+impl Settings {
+    pub fn build(&self, channels: usize) -> Result<Width, Error> {
+        mid_side::require_stereo(channels)?;
+        if self.width < 0.0 {
+            return Err(Error::InvalidArgument(
+                "Width must not be negative.".into(),
+            ));
+        }
+
+        Ok(Width { width: self.width })
+    }
+
+    pub fn widen<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut width = self.build(input.spec().channels as usize)?;
+        super::apply(&mut width, input, output, "Processing sample")
+    }
+}
+
+/// Stereo width control which scales the side signal
+#[derive(Debug, Clone)]
+pub struct Width {
+    width: f32,
+}
+
+impl Effect for Width {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != 2 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let (mid, side) = mid_side::encode(frame);
+        Ok(mid_side::decode(mid, self.width * side))
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {}
+
+    fn output_channels(&self) -> usize {
+        2
+    }
+}
+
+#[test]
+fn test_width() {
+    let frame = [0.5, -0.25];
+    let process = |width| Settings { width }.build(2).unwrap().process(&frame);
+
+    let mono = process(0.0).unwrap();
+    assert_eq!(mono[0], mono[1]);
+    assert_eq!(mono[0], 0.125);
+    assert_eq!(process(1.0).unwrap(), frame);
+
+    // Doubling the width doubles the side signal and keeps the mid.
+    let wide = process(2.0).unwrap();
+    assert_eq!(mid_side::encode(&wide), (0.125, 0.75));
+
+    assert!(matches!(
+        Settings { width: -0.5 }.build(2),
+        Err(Error::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,286 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::error::Error;
+use std::f64::consts::FRAC_PI_2;
+
+/// Shape of a fade or of an envelope segment.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    /// Linear gain
+    Linear,
+    /// Slow start and fast end, linear in dB over 60 dB
+    Exponential,
+    /// Fast start and slow end
+    Logarithmic,
+    /// Raised cosine S-curve with slow start and end
+    #[value(alias = "s-curve")]
+    #[serde(alias = "s-curve")]
+    Cosine,
+    /// Sine shape whose fade-in and fade-out have constant summed power
+    EqualPower,
+}
+
+impl Curve {
+    /// Rising curve value from 0 to 1 for t from 0 to 1.
+    pub fn shape(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::Exponential => {
+                (10.0_f64.powf(3.0 * (t - 1.0)) - 1e-3) / (1.0 - 1e-3)
+            }
+            Self::Logarithmic => (1.0 + 9.0 * t).log10(),
+            Self::Cosine => 0.5 - 0.5 * (std::f64::consts::PI * t).cos(),
+            Self::EqualPower => (FRAC_PI_2 * t).sin(),
+        }
+    }
+
+    /// Interpolates from a to b, falling segments use the mirrored curve
+    /// so that fade-outs are the time reverse of fade-ins.
+    pub fn interpolate(&self, a: f64, b: f64, t: f64) -> f64 {
+        if b >= a {
+            a + (b - a) * self.shape(t)
+        } else {
+            b + (a - b) * self.shape(1.0 - t)
+        }
+    }
+}
+
+/// Time position in seconds, milliseconds or samples, e.g. "1.5", "1.5s",
+/// "200ms" or "48000samples".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Seconds(f64),
+    Samples(usize),
+}
+
+impl Position {
+    /// Position as sample index rounded to the nearest sample.
+    pub fn samples(&self, fs: f64) -> usize {
+        match self {
+            Self::Seconds(x) => (x * fs).round() as usize,
+            Self::Samples(x) => *x,
+        }
+    }
+}
+
+impl std::str::FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse = |x: &str| {
+            x.parse::<f64>()
+                .ok()
+                .filter(|x| *x >= 0.0)
+                .ok_or(format!("Invalid position '{}'", s))
+        };
+        if let Some(x) = s.strip_suffix("samples") {
+            x.parse()
+                .map(Self::Samples)
+                .map_err(|_| format!("Invalid position '{}'", s))
+        } else if let Some(x) = s.strip_suffix("ms") {
+            Ok(Self::Seconds(parse(x)? / 1000.0))
+        } else {
+            Ok(Self::Seconds(parse(s.strip_suffix('s').unwrap_or(s))?))
+        }
+    }
+}
+
+/// Envelope point, the curve shapes the segment to the next point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub position: usize,
+    pub value: f64,
+    pub curve: Curve,
+}
+
+/// Sample accurate breakpoint envelope, e.g. the gain in dB of the
+/// envelope effect.
+/// The value is constant before the first and after the last point.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Envelope {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Result<Self, Error> {
+        if breakpoints.is_empty() {
+            return Err(Error::InvalidArgument(
+                "Envelope requires at least one point.".into(),
+            ));
+        }
+        if breakpoints
+            .windows(2)
+            .any(|x| x[0].position > x[1].position)
+        {
+            return Err(Error::InvalidArgument(
+                "Envelope points must be in ascending order.".into(),
+            ));
+        }
+
+        Ok(Self { breakpoints })
+    }
+
+    /// Loads an envelope from a text file with one point per line as
+    /// "position value [curve]", e.g. "1.5 -6 cosine". Empty lines and
+    /// lines starting with '#' are ignored.
+    pub fn load(filename: &str, fs: f64) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(filename)?;
+        Self::parse(&text, filename, fs)
+    }
+
+    /// Parses the points of an envelope file, errors are reported with
+    /// the file name and line number.
+    fn parse(text: &str, filename: &str, fs: f64) -> Result<Self, Error> {
+        let mut breakpoints = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |e: String| {
+                Error::InvalidArgument(format!(
+                    "{}:{}: {}",
+                    filename,
+                    number + 1,
+                    e
+                ))
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 2 || fields.len() > 3 {
+                return Err(invalid(
+                    "Expected \"position value [curve]\"".into(),
+                ));
+            }
+            let position: Position = fields[0].parse().map_err(invalid)?;
+            let value = fields[1].parse().map_err(|_| {
+                invalid(format!("Invalid value '{}'", fields[1]))
+            })?;
+            let curve = match fields.get(2) {
+                Some(x) => <Curve as clap::ValueEnum>::from_str(x, true)
+                    .map_err(invalid)?,
+                None => Curve::Linear,
+            };
+            breakpoints.push(Breakpoint {
+                position: position.samples(fs),
+                value,
+                curve,
+            });
+        }
+
+        Self::new(breakpoints)
+    }
+
+    /// Envelope value at the given sample index.
+    pub fn value(&self, position: usize) -> f64 {
+        let points = &self.breakpoints;
+        let i = points.partition_point(|x| x.position <= position);
+        if i == 0 {
+            return points[0].value;
+        }
+        if i == points.len() {
+            return points[i - 1].value;
+        }
+
+        let (a, b) = (&points[i - 1], &points[i]);
+        let t =
+            (position - a.position) as f64 / (b.position - a.position) as f64;
+        a.curve.interpolate(a.value, b.value, t)
+    }
+}
+
+#[test]
+fn test_envelope() {
+    for curve in [
+        Curve::Linear,
+        Curve::Exponential,
+        Curve::Logarithmic,
+        Curve::Cosine,
+        Curve::EqualPower,
+    ] {
+        assert!(curve.shape(0.0).abs() < 1e-12);
+        assert!((curve.shape(1.0) - 1.0).abs() < 1e-12);
+        assert!(
+            (curve.interpolate(1.0, 0.0, 0.25) - curve.shape(0.75)).abs()
+                < 1e-12
+        );
+    }
+    let (fade_in, fade_out) = (
+        Curve::EqualPower.interpolate(0.0, 1.0, 0.3),
+        Curve::EqualPower.interpolate(1.0, 0.0, 0.3),
+    );
+    assert!((fade_in.powi(2) + fade_out.powi(2) - 1.0).abs() < 1e-12);
+
+    let envelope = Envelope::new(vec![
+        Breakpoint {
+            position: 10,
+            value: 0.0,
+            curve: Curve::Linear,
+        },
+        Breakpoint {
+            position: 20,
+            value: -6.0,
+            curve: Curve::Linear,
+        },
+    ])
+    .unwrap();
+    assert_eq!(envelope.value(0), 0.0);
+    assert_eq!(envelope.value(15), -3.0);
+    assert_eq!(envelope.value(20), -6.0);
+    assert_eq!(envelope.value(100), -6.0);
+
+    let text = "# position gain_db [curve]\n\n0 0\n10.5 0 cosine\n12 -6\n";
+    let envelope = Envelope::parse(text, "ride.txt", 1000.0).unwrap();
+    assert_eq!(
+        envelope.breakpoints,
+        [
+            Breakpoint {
+                position: 0,
+                value: 0.0,
+                curve: Curve::Linear,
+            },
+            Breakpoint {
+                position: 10500,
+                value: 0.0,
+                curve: Curve::Cosine,
+            },
+            Breakpoint {
+                position: 12000,
+                value: -6.0,
+                curve: Curve::Linear,
+            },
+        ]
+    );
+    for text in ["", "1", "1 -6 cosine x", "1 x", "x 0", "1 0 square"] {
+        assert!(Envelope::parse(text, "ride.txt", 1000.0).is_err());
+    }
+    assert!(Envelope::parse("2 0\n1 0", "ride.txt", 1000.0).is_err());
+    match Envelope::parse("0 0\n1 x", "ride.txt", 1000.0) {
+        Err(Error::InvalidArgument(e)) => assert!(e.starts_with("ride.txt:2:")),
+        _ => panic!("Expected an invalid argument"),
+    }
+
+    assert_eq!("200ms".parse(), Ok(Position::Seconds(0.2)));
+    assert_eq!("48000samples".parse(), Ok(Position::Samples(48000)));
+    assert_eq!("1.5s".parse::<Position>().unwrap().samples(48000.0), 72000);
+}
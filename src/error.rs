@@ -15,12 +15,22 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
+#[derive(Debug)]
 pub enum Error {
     Denormalized,
     InvalidFrame,
     InvalidArgument(String),
     Io(std::io::Error),
     Hound(hound::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Png(png::EncodingError),
+    Flac(String),
+    Step {
+        index: usize,
+        name: &'static str,
+        error: Box<Error>,
+    },
 }
 
 impl std::fmt::Display for Error {
@@ -37,10 +47,25 @@ impl std::fmt::Display for Error {
                 write!(f, "Invalid argument: {}", e)
             }
             Self::Io(e) => {
-                write!(f, "IO Error: {}", e.to_string())
+                write!(f, "IO Error: {}", e)
             }
             Self::Hound(e) => {
-                write!(f, "{}", e.to_string())
+                write!(f, "{}", e)
+            }
+            Self::Json(e) => {
+                write!(f, "JSON Error: {}", e)
+            }
+            Self::Toml(e) => {
+                write!(f, "TOML Error: {}", e)
+            }
+            Self::Png(e) => {
+                write!(f, "PNG Error: {}", e)
+            }
+            Self::Flac(e) => {
+                write!(f, "FLAC Error: {}", e)
+            }
+            Self::Step { index, name, error } => {
+                write!(f, "Step {} ({}) failed: {}", index + 1, name, error)
             }
         }
     }
@@ -57,3 +82,27 @@ impl From<hound::Error> for Error {
         Self::Hound(e)
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(e: png::EncodingError) -> Self {
+        Self::Png(e)
+    }
+}
+
+impl From<claxon::Error> for Error {
+    fn from(e: claxon::Error) -> Self {
+        Self::Flac(e.to_string())
+    }
+}
@@ -38,6 +38,140 @@ impl Biquad {
             output: [0.0; 2],
         }
     }
+
+    /// Constructs a filter from un-normalized coefficients
+    /// B0, B1, B2 and A0, A1, A2.
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self::new(
+            [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            [a[1] / a[0], a[2] / a[0]],
+        )
+    }
+
+    /// Returns the normalized angular frequency w0 and the bandwidth
+    /// parameter alpha from the audio EQ cookbook.
+    fn omega_alpha(fs: f64, f0: f64, q: f64) -> (f64, f64) {
+        let w0 = 2.0 * std::f64::consts::PI * f0 / fs;
+        (w0, w0.sin() / (2.0 * q))
+    }
+
+    // Filter designs after Robert Bristow-Johnson,
+    // "Cookbook formulae for audio EQ biquad filter coefficients".
+
+    /// Second order low pass filter with cutoff frequency "f0".
+    pub fn low_pass(fs: f64, f0: f64, q: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        Self::normalized(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Second order high pass filter with cutoff frequency "f0".
+    pub fn high_pass(fs: f64, f0: f64, q: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        Self::normalized(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Band pass filter with 0 dB peak gain at centre frequency "f0".
+    pub fn band_pass(fs: f64, f0: f64, q: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        Self::normalized(
+            [alpha, 0.0, -alpha],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Notch filter at centre frequency "f0".
+    pub fn notch(fs: f64, f0: f64, q: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        Self::normalized(
+            [1.0, -2.0 * cos, 1.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// All pass filter with 180 degree phase shift at frequency "f0".
+    pub fn all_pass(fs: f64, f0: f64, q: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        Self::normalized(
+            [1.0 - alpha, -2.0 * cos, 1.0 + alpha],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    /// Peaking filter with "gain_db" at centre frequency "f0".
+    pub fn peaking(fs: f64, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        let a = 10.0_f64.powf(gain_db / 40.0);
+        Self::normalized(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        )
+    }
+
+    /// Low shelf filter with "gain_db" below the midpoint frequency "f0".
+    pub fn low_shelf(fs: f64, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        let a = 10.0_f64.powf(gain_db / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    /// High shelf filter with "gain_db" above the midpoint frequency "f0".
+    pub fn high_shelf(fs: f64, f0: f64, q: f64, gain_db: f64) -> Self {
+        let (w0, alpha) = Self::omega_alpha(fs, f0, q);
+        let cos = w0.cos();
+        let a = 10.0_f64.powf(gain_db / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + beta,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    /// Returns the magnitude response at frequency "f" in linear units.
+    #[cfg(test)]
+    pub fn magnitude(&self, fs: f64, f: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * f / fs;
+        // Evaluate numerator and denominator polynomials at z = e^(jw).
+        let (c1, s1) = (w.cos(), -w.sin());
+        let (c2, s2) = ((2.0 * w).cos(), -(2.0 * w).sin());
+        let num_re = self.b[0] + self.b[1] * c1 + self.b[2] * c2;
+        let num_im = self.b[1] * s1 + self.b[2] * s2;
+        let den_re = 1.0 + self.a[0] * c1 + self.a[1] * c2;
+        let den_im = self.a[0] * s1 + self.a[1] * s2;
+        (num_re.hypot(num_im)) / (den_re.hypot(den_im))
+    }
 }
 
 impl Filter for Biquad {
@@ -57,3 +191,47 @@ impl Filter for Biquad {
         output
     }
 }
+
+#[test]
+fn test_biquad_designs() {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    let fs = 48000.0;
+    let f0 = 1000.0;
+    let db = |x: f64| 20.0 * x.log10();
+
+    for gain_db in [-12.0, -3.0, 6.0, 12.0] {
+        let peaking = Biquad::peaking(fs, f0, 1.4, gain_db);
+        assert!((db(peaking.magnitude(fs, f0)) - gain_db).abs() < 1e-9);
+        assert!(db(peaking.magnitude(fs, 20.0)).abs() < 0.1);
+
+        let low_shelf = Biquad::low_shelf(fs, f0, FRAC_1_SQRT_2, gain_db);
+        assert!((db(low_shelf.magnitude(fs, f0)) - gain_db / 2.0).abs() < 1e-9);
+        assert!((db(low_shelf.magnitude(fs, 0.0)) - gain_db).abs() < 1e-9);
+
+        let high_shelf = Biquad::high_shelf(fs, f0, FRAC_1_SQRT_2, gain_db);
+        assert!(
+            (db(high_shelf.magnitude(fs, f0)) - gain_db / 2.0).abs() < 1e-9
+        );
+        assert!(
+            (db(high_shelf.magnitude(fs, fs / 2.0)) - gain_db).abs() < 1e-9
+        );
+    }
+
+    let q = FRAC_1_SQRT_2;
+    let low_pass = Biquad::low_pass(fs, f0, q);
+    assert!((db(low_pass.magnitude(fs, f0)) - db(q)).abs() < 1e-9);
+    assert!((low_pass.magnitude(fs, 0.0) - 1.0).abs() < 1e-9);
+    let high_pass = Biquad::high_pass(fs, f0, q);
+    assert!((db(high_pass.magnitude(fs, f0)) - db(q)).abs() < 1e-9);
+    assert!(high_pass.magnitude(fs, 0.0) < 1e-9);
+
+    let band_pass = Biquad::band_pass(fs, f0, 2.0);
+    assert!((band_pass.magnitude(fs, f0) - 1.0).abs() < 1e-9);
+    let notch = Biquad::notch(fs, f0, 2.0);
+    assert!(notch.magnitude(fs, f0) < 1e-9);
+    let all_pass = Biquad::all_pass(fs, f0, 2.0);
+    for f in [20.0, f0, 10000.0] {
+        assert!((all_pass.magnitude(fs, f) - 1.0).abs() < 1e-9);
+    }
+}
@@ -50,6 +50,34 @@ impl Fir {
             buf: VecDeque::from(vec![0.0; n as usize + 1]),
         }
     }
+
+    /// Construct a linear-phase low-pass FIR filter with cutoff frequency
+    /// "f0" and an odd number of "taps" from a Blackman windowed sinc.
+    pub fn low_pass(fs: f64, f0: f64, taps: usize) -> Fir {
+        let taps = taps | 1;
+        let center = (taps / 2) as f64;
+        let wc = 2.0 * std::f64::consts::PI * f0 / fs;
+        let b = (0..taps)
+            .map(|k| {
+                let n = k as f64 - center;
+                let sinc = if n == 0.0 {
+                    wc / std::f64::consts::PI
+                } else {
+                    (wc * n).sin() / (std::f64::consts::PI * n)
+                };
+                let phase =
+                    2.0 * std::f64::consts::PI * k as f64 / (taps - 1) as f64;
+                let window =
+                    0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+                sinc * window
+            })
+            .collect();
+
+        Self {
+            b,
+            buf: VecDeque::from(vec![0.0; taps]),
+        }
+    }
 }
 
 impl Filter for Fir {
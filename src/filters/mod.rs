@@ -18,8 +18,10 @@
 pub mod biquad;
 pub mod fir;
 pub mod lag1;
+pub mod mov_avg;
 pub mod mov_max;
 pub mod mov_rms;
+pub mod resampler;
 
 pub trait Filter: std::fmt::Debug {
     fn process(&mut self, input: f64) -> f64;
@@ -0,0 +1,82 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use super::Filter;
+use std::collections::VecDeque;
+
+/// Moving average filter
+#[derive(Clone, Debug)]
+pub struct MovAvg {
+    /// Internal accumulator
+    acc: f64,
+    /// Insertion counter
+    counter: usize,
+    /// Window buffer for refreshing
+    buffer: VecDeque<f64>,
+}
+
+impl MovAvg {
+    /// Internal accumulator refresh interval
+    const REFRESH_INTERVAL: usize = 1048576; // 1 MB
+
+    /// Constructs a moving average filter with the given window length
+    /// and all values in the window set to "initial".
+    pub fn new(window_length: usize, initial: f64) -> Self {
+        let buffer = VecDeque::from(vec![initial; window_length.max(1)]);
+        Self {
+            acc: buffer.iter().sum(),
+            counter: 0,
+            buffer,
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.acc = self.buffer.iter().sum();
+        self.counter = 0;
+    }
+}
+
+impl Filter for MovAvg {
+    fn process(&mut self, input: f64) -> f64 {
+        let old_input = self.buffer.pop_front().unwrap_or(0.0);
+        self.buffer.push_back(input);
+
+        if self.counter > Self::REFRESH_INTERVAL {
+            // Recalculate sum from window buffer to avoid accumulation of
+            // rounding errors.
+            self.refresh();
+        } else {
+            self.counter += 1;
+            self.acc -= old_input;
+            self.acc += input;
+        }
+
+        self.acc / (self.buffer.len() as f64)
+    }
+}
+
+#[test]
+fn test_mov_avg() {
+    let mut mov_avg = MovAvg::new(4, 1.0);
+    let data = [1.0, 0.0, -1.0, 0.0, 0.5, 0.0, -0.5, 0.0];
+
+    let avg: Vec<f64> = data.iter().map(|x| mov_avg.process(*x)).collect();
+
+    assert_eq!(avg, vec![1.0, 0.75, 0.25, 0.0, -0.125, -0.125, 0.0, 0.0]);
+    mov_avg.refresh();
+    assert_eq!(mov_avg.acc, 0.0);
+}
@@ -0,0 +1,316 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::error::Error;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Quality {
+    /// 60 dB stopband rejection, short filter
+    Low,
+    /// 100 dB stopband rejection
+    Medium,
+    /// 140 dB stopband rejection, long filter
+    High,
+}
+
+impl Quality {
+    /// Filter half length in zero crossings and stopband attenuation in dB.
+    fn design(&self) -> (f64, f64) {
+        match self {
+            Self::Low => (24.0, 60.0),
+            Self::Medium => (64.0, 100.0),
+            Self::High => (128.0, 140.0),
+        }
+    }
+
+    /// Transition band width relative to the lower sample rate.
+    /// The stopband starts at the lower Nyquist frequency.
+    pub fn transition_width(&self) -> f64 {
+        let (zero_crossings, attenuation) = self.design();
+        // Kaiser filter length estimate for a cutoff of about fs / 2
+        (attenuation - 7.95) / (28.72 * zero_crossings)
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-16 {
+        term *= (x / (2.0 * k)).powi(2);
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Polyphase windowed-sinc resampler for rational ratios.
+/// The prototype low pass filter runs at the least common multiple
+/// of both sample rates and uses a Kaiser window.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Upsampling factor
+    up: usize,
+    /// Downsampling factor
+    down: usize,
+    /// Polyphase filter bank, phase p holds h[p + k * up]
+    phases: Vec<Vec<f64>>,
+    /// Input history of each channel, most recent sample first
+    history: Vec<VecDeque<f64>>,
+    /// Number of processed input frames
+    input_count: usize,
+    /// Index of the next output frame
+    output_index: usize,
+    /// Filter delay in output frames
+    latency: usize,
+}
+
+impl Resampler {
+    /// Maximum up- or downsampling factor of the reduced ratio. The filter
+    /// length grows with both factors, so e.g. nearly co-prime rates would
+    /// need a huge filter bank.
+    const MAX_PHASES: usize = 8192;
+
+    pub fn new(
+        fs_in: u32,
+        fs_out: u32,
+        channels: usize,
+        quality: Quality,
+    ) -> Result<Self, Error> {
+        if fs_in == 0 || fs_out == 0 {
+            return Err(Error::InvalidArgument(
+                "Sample rates must be greater than zero.".into(),
+            ));
+        }
+        let divisor = gcd(fs_in as usize, fs_out as usize);
+        let up = fs_out as usize / divisor;
+        let down = fs_in as usize / divisor;
+        if up.max(down) > Self::MAX_PHASES {
+            return Err(Error::InvalidArgument(format!(
+                "Sample rate ratio {}:{} requires more than {} filter phases.",
+                up,
+                down,
+                Self::MAX_PHASES
+            )));
+        }
+
+        let (zero_crossings, attenuation) = quality.design();
+        let beta = if attenuation > 50.0 {
+            0.1102 * (attenuation - 8.7)
+        } else {
+            0.5842 * (attenuation - 21.0).powf(0.4)
+                + 0.07886 * (attenuation - 21.0)
+        };
+        // Cutoff relative to the sample rate of the prototype filter
+        let scale = (up as f64 / down as f64).min(1.0) / up as f64;
+        let cutoff = (0.5 - quality.transition_width() / 2.0) * scale;
+        let half_len = (zero_crossings / (2.0 * cutoff)).ceil() as usize;
+
+        // Center the filter on a multiple of the downsampling factor
+        // so that the latency is a whole number of output frames.
+        let latency = (half_len + down - 1) / down;
+        let center = latency * down;
+        let taps = (center + half_len + up) / up;
+        let h = (0..taps * up).map(|n| {
+            let t = n as f64 - center as f64;
+            if t.abs() > half_len as f64 {
+                return 0.0;
+            }
+            let x = 2.0 * cutoff * t;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+            };
+            let w = t / half_len as f64;
+            let window =
+                bessel_i0(beta * (1.0 - w * w).sqrt()) / bessel_i0(beta);
+            // Gain of "up" compensates the zero stuffing.
+            up as f64 * 2.0 * cutoff * sinc * window
+        });
+        let mut phases = vec![Vec::with_capacity(taps); up];
+        for (n, x) in h.enumerate() {
+            phases[n % up].push(x);
+        }
+
+        Ok(Self {
+            up,
+            down,
+            phases,
+            history: vec![VecDeque::from(vec![0.0; taps]); channels],
+            input_count: 0,
+            output_index: 0,
+            latency,
+        })
+    }
+
+    /// Filter delay in output frames.
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /// Number of output frames which correspond to the given number
+    /// of input frames.
+    pub fn output_len(&self, input_len: usize) -> usize {
+        (input_len * self.up + self.down - 1) / self.down
+    }
+
+    /// Processes one input frame and returns all output frames
+    /// which became available.
+    pub fn process(&mut self, frame: &[f32]) -> Result<Vec<Vec<f32>>, Error> {
+        if frame.len() != self.history.len() {
+            return Err(Error::InvalidFrame);
+        }
+        for (history, x) in self.history.iter_mut().zip(frame.iter()) {
+            history.pop_back();
+            history.push_front(*x as f64);
+        }
+        self.input_count += 1;
+
+        let mut output = Vec::new();
+        loop {
+            // Position of the next output frame at the prototype rate
+            let t = self.output_index * self.down;
+            if t / self.up >= self.input_count {
+                break;
+            }
+            let phase = &self.phases[t % self.up];
+            output.push(
+                self.history
+                    .iter()
+                    .map(|history| {
+                        phase
+                            .iter()
+                            .zip(history.iter())
+                            .fold(0.0, |acc, (h, x)| acc + h * x)
+                            as f32
+                    })
+                    .collect(),
+            );
+            self.output_index += 1;
+        }
+
+        Ok(output)
+    }
+}
+
+/// Resamples a sine and returns its output amplitude, measured by
+/// a Hann windowed projection after the filter settled.
+#[cfg(test)]
+fn sine_response(fs_in: u32, fs_out: u32, f: f64, quality: Quality) -> f64 {
+    use crate::generator::{Generator, Signal};
+
+    let mut resampler = Resampler::new(fs_in, fs_out, 1, quality).unwrap();
+    let len = fs_in as usize / 2;
+    let signal = Signal::Sine {
+        frequency: f,
+        phase: 0.0,
+    };
+    let input = Generator::new(&signal, fs_in as f64, 1, 0.0, len).unwrap();
+    let mut output = Vec::new();
+    for x in input {
+        for frame in resampler.process(&x).unwrap() {
+            output.push(frame[0] as f64);
+        }
+    }
+
+    // Skip the latency and the filter settling time.
+    let start = resampler.latency() + fs_out as usize / 16;
+    let end = output.len() - fs_out as usize / 16;
+    let omega = 2.0 * std::f64::consts::PI * f / fs_out as f64;
+    let (mut re, mut im, mut weight) = (0.0, 0.0, 0.0);
+    for (i, y) in output.iter().enumerate().take(end).skip(start) {
+        let w = 0.5
+            - 0.5
+                * (2.0 * std::f64::consts::PI * (i - start) as f64
+                    / (end - start) as f64)
+                    .cos();
+        // Output frame i corresponds to input time i - latency.
+        let phase = omega * (i - resampler.latency()) as f64;
+        re += w * y * phase.sin();
+        im += w * y * phase.cos();
+        weight += w;
+    }
+    2.0 * (re * re + im * im).sqrt() / weight
+}
+
+#[test]
+fn test_resampler_passband_ripple() {
+    for (fs_in, fs_out) in [(44100, 48000), (48000, 44100), (48000, 96000)] {
+        for quality in [Quality::Low, Quality::Medium, Quality::High] {
+            let fs_min = fs_in.min(fs_out) as f64;
+            let edge = (0.5 - quality.transition_width()) * fs_min;
+            // Stepped sine sweep through the passband
+            let mut f = 100.0;
+            while f < edge {
+                let gain = sine_response(fs_in, fs_out, f, quality);
+                assert!(
+                    (20.0 * gain.log10()).abs() < 0.01,
+                    "{} -> {} Hz, {:?}: {} Hz has {} dB",
+                    fs_in,
+                    fs_out,
+                    quality,
+                    f,
+                    20.0 * gain.log10()
+                );
+                f *= 2.0;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_resampler_stopband_rejection() {
+    for (quality, limit) in [
+        (Quality::Low, -58.0),
+        (Quality::Medium, -98.0),
+        (Quality::High, -130.0),
+    ] {
+        // Stepped sine sweep above the output Nyquist frequency
+        for f in [22100.0, 22500.0, 23000.0, 23900.0] {
+            let gain = sine_response(48000, 44100, f, quality);
+            assert!(
+                20.0 * gain.log10() < limit,
+                "{:?}: {} Hz has {} dB",
+                quality,
+                f,
+                20.0 * gain.log10()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_resampler_ratio_limit() {
+    assert!(Resampler::new(44056, 48000, 1, Quality::High).is_ok());
+    for (fs_in, fs_out) in [(44100, 44101), (96001, 48000), (48000, 1)] {
+        match Resampler::new(fs_in, fs_out, 1, Quality::Low) {
+            Err(Error::InvalidArgument(_)) => (),
+            x => panic!("{} -> {} Hz: {:?}", fs_in, fs_out, x.map(|_| ())),
+        }
+    }
+}
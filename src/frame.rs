@@ -17,6 +17,9 @@
 \******************************************************************************/
 use hound::{Error, Sample};
 
+/// Speaker position of a channel. The order follows the bits of the
+/// WAVE_FORMAT_EXTENSIBLE channel mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum ChannelMap {
     Left,
     Right,
@@ -24,8 +27,71 @@ pub enum ChannelMap {
     Lfe,
     RearLeft,
     RearRight,
+    LeftCenter,
+    RightCenter,
+    RearCenter,
     SideLeft,
     SideRight,
+    /// Any height channel
+    Top,
+}
+
+impl ChannelMap {
+    /// Speaker positions in channel mask bit order
+    const MASK_ORDER: [Self; 11] = [
+        Self::Left,
+        Self::Right,
+        Self::Center,
+        Self::Lfe,
+        Self::RearLeft,
+        Self::RearRight,
+        Self::LeftCenter,
+        Self::RightCenter,
+        Self::RearCenter,
+        Self::SideLeft,
+        Self::SideRight,
+    ];
+
+    /// Maps a WAVE_FORMAT_EXTENSIBLE channel mask to speaker positions.
+    /// Returns None if the mask does not match the number of channels.
+    pub fn from_mask(mask: u32, channels: usize) -> Option<Vec<Self>> {
+        let layout: Vec<Self> = (0..18)
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| *Self::MASK_ORDER.get(i).unwrap_or(&Self::Top))
+            .collect();
+        if layout.len() == channels {
+            Some(layout)
+        } else {
+            None
+        }
+    }
+
+    /// Default layout which assigns the channel mask bits in order,
+    /// e.g. L, R, C, LFE, Ls, Rs for six channels.
+    pub fn default_layout(channels: usize) -> Vec<Self> {
+        (0..channels)
+            .map(|i| *Self::MASK_ORDER.get(i).unwrap_or(&Self::Top))
+            .collect()
+    }
+
+    /// ITU-R BS.1770 channel weights. Channels with an azimuth within
+    /// 60° and 120° are amplified by 1.5 dB, the LFE is excluded.
+    pub fn weights(layout: &[Self]) -> Vec<f64> {
+        // Rear channels are surround channels at 110° unless there are
+        // side channels, e.g. the back channels of 7.1 at 135° to 150°.
+        let has_sides = layout
+            .iter()
+            .any(|x| matches!(x, Self::SideLeft | Self::SideRight));
+        layout
+            .iter()
+            .map(|x| match x {
+                Self::Lfe => 0.0,
+                Self::SideLeft | Self::SideRight => 1.41,
+                Self::RearLeft | Self::RearRight if !has_sides => 1.41,
+                _ => 1.0,
+            })
+            .collect()
+    }
 }
 
 pub struct FrameIterator<S, T> {
@@ -62,3 +128,34 @@ where
         Some(Ok(&self.buffer))
     }
 }
+
+#[test]
+fn test_channel_map_weights() {
+    use ChannelMap::*;
+
+    // 5.1 with side surrounds and LFE in the middle of the channel order
+    let layout = ChannelMap::from_mask(0x60f, 6).unwrap();
+    assert_eq!(layout, [Left, Right, Center, Lfe, SideLeft, SideRight]);
+    assert_eq!(
+        ChannelMap::weights(&layout),
+        [1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    );
+
+    // 5.1 with rear surrounds
+    let layout = ChannelMap::from_mask(0x3f, 6).unwrap();
+    assert_eq!(
+        ChannelMap::weights(&layout),
+        [1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    );
+
+    // 7.1 back channels are behind the surround zone.
+    let layout = ChannelMap::from_mask(0x63f, 8).unwrap();
+    assert_eq!(
+        ChannelMap::weights(&layout),
+        [1.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.41, 1.41]
+    );
+
+    // Mask does not match the channel count.
+    assert!(ChannelMap::from_mask(0x3f, 2).is_none());
+    assert_eq!(ChannelMap::default_layout(4), [Left, Right, Center, Lfe]);
+}
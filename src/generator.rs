@@ -0,0 +1,497 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::conversion::FrameWriter;
+use crate::envelope::Position;
+use crate::error::Error;
+use crate::progress::Progress;
+use hound::{SampleFormat, WavSpec};
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Signal {
+    /// Sine tone
+    Sine {
+        /// Frequency in Hz
+        #[arg(default_value_t = 1000.0)]
+        frequency: f64,
+        /// Start phase in degrees
+        #[arg(long, default_value_t = 0.0)]
+        phase: f64,
+    },
+    /// Sum of sine tones with equal amplitude
+    MultiTone {
+        /// Comma separated frequencies in Hz
+        #[arg(required = true, value_delimiter = ',')]
+        frequencies: Vec<f64>,
+    },
+    /// Logarithmic sine sweep
+    Sweep {
+        /// Start frequency in Hz
+        #[arg(default_value_t = 20.0)]
+        start: f64,
+        /// End frequency in Hz
+        #[arg(default_value_t = 20000.0)]
+        end: f64,
+    },
+    /// Uncorrelated white noise with the level as RMS
+    WhiteNoise,
+    /// Uncorrelated pink noise with the level as RMS
+    PinkNoise,
+    /// Single impulse at the beginning
+    Impulse,
+    /// Digital silence
+    Silence,
+    /// EBU Tech 3341 loudness test case 1 to 6 or 9. The test case defines
+    /// level, duration and channels.
+    Ebu3341 {
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=9))]
+        case: u8,
+    },
+    /// EBU Tech 3342 loudness range test case 1 to 4. The test case
+    /// defines level, duration and channels.
+    Ebu3342 {
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=4))]
+        case: u8,
+    },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+#[command(allow_negative_numbers = true)]
+pub struct Settings {
+    /// Sample rate in Hz
+    #[arg(long, default_value_t = 48000)]
+    sample_rate: u32,
+    /// Number of channels
+    #[arg(long, default_value_t = 2)]
+    channels: u16,
+    /// Peak level in dBFS, RMS level for noise
+    #[arg(long, default_value_t = -20.0)]
+    level: f64,
+    /// Duration in seconds or with unit suffix s, ms or samples
+    #[arg(long, default_value = "10")]
+    duration: Position,
+    #[command(subcommand)]
+    signal: Signal,
+}
+
+impl Settings {
+    pub fn build(&self) -> Result<Generator, Error> {
+        let fs = self.sample_rate as f64;
+        match self.signal {
+            Signal::Ebu3341 { case } => Generator::ebu3341(case, fs),
+            Signal::Ebu3342 { case } => Generator::ebu3342(case, fs),
+            _ => Generator::new(
+                &self.signal,
+                fs,
+                self.channels as usize,
+                self.level,
+                self.duration.samples(fs),
+            ),
+        }
+    }
+}
+
+/// Part of the signal with constant channel levels.
+#[derive(Debug, Clone)]
+struct Segment {
+    /// Exclusive end sample index
+    end: usize,
+    /// Linear gain of each channel
+    gain: Vec<f64>,
+}
+
+/// Xorshift random number generator
+#[derive(Debug, Clone)]
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Uniform random number with unit variance
+    fn next(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let x = (self.state >> 11) as f64 / (1_u64 << 53) as f64 - 0.5;
+        x * 12.0_f64.sqrt()
+    }
+}
+
+/// Paul Kellet's refined pink noise filter
+#[derive(Debug, Clone, Default)]
+struct PinkFilter {
+    b: [f64; 7],
+}
+
+impl PinkFilter {
+    fn process(&mut self, x: f64) -> f64 {
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + x * 0.0555179;
+        b[1] = 0.99332 * b[1] + x * 0.0750759;
+        b[2] = 0.96900 * b[2] + x * 0.1538520;
+        b[3] = 0.86650 * b[3] + x * 0.3104856;
+        b[4] = 0.55000 * b[4] + x * 0.5329522;
+        b[5] = -0.7616 * b[5] - x * 0.0168980;
+        let y = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + x * 0.5362;
+        b[6] = x * 0.115926;
+        y
+    }
+
+    /// RMS gain of the filter for white noise
+    fn rms_gain() -> f64 {
+        let mut filter = Self::default();
+        let mut energy = filter.process(1.0).powi(2);
+        for _ in 0..(1 << 16) {
+            energy += filter.process(0.0).powi(2);
+        }
+        energy.sqrt()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    /// Tones with the start phase in radians
+    Tones {
+        frequencies: Vec<f64>,
+        phase: f64,
+    },
+    Sweep {
+        start: f64,
+        rate: f64,
+    },
+    WhiteNoise(Random),
+    PinkNoise {
+        random: Random,
+        filters: Vec<PinkFilter>,
+        gain: f64,
+    },
+    Impulse,
+    Silence,
+}
+
+impl Source {
+    fn sample(&mut self, fs: f64, channel: usize, index: usize) -> f64 {
+        let t = index as f64 / fs;
+        match self {
+            Self::Tones { frequencies, phase } => {
+                frequencies
+                    .iter()
+                    .map(|f| (2.0 * PI * f * t + *phase).sin())
+                    .sum::<f64>()
+                    / frequencies.len() as f64
+            }
+            Self::Sweep { start, rate } => {
+                (2.0 * PI * *start * ((*rate * t).exp() - 1.0) / *rate).sin()
+            }
+            Self::WhiteNoise(random) => random.next(),
+            Self::PinkNoise {
+                random,
+                filters,
+                gain,
+            } => filters[channel].process(random.next()) / *gain,
+            Self::Impulse => (index == 0) as u8 as f64,
+            Self::Silence => 0.0,
+        }
+    }
+}
+
+/// Test signal generator which yields one frame per iteration.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    fs: f64,
+    channels: usize,
+    source: Source,
+    segments: Vec<Segment>,
+    /// Current sample index
+    index: usize,
+    /// Current segment index
+    segment: usize,
+}
+
+impl Generator {
+    /// Constructs a generator for the signal with the level in dBFS and
+    /// the duration in samples.
+    pub fn new(
+        signal: &Signal,
+        fs: f64,
+        channels: usize,
+        level_db: f64,
+        duration: usize,
+    ) -> Result<Self, Error> {
+        let nyquist = |f: f64| {
+            if f <= 0.0 || f >= fs / 2.0 {
+                Err(Error::InvalidArgument(format!(
+                    "Frequency {} Hz must be between zero and half the \
+                     sampling rate.",
+                    f
+                )))
+            } else {
+                Ok(f)
+            }
+        };
+        let random = Random {
+            state: 0x2545_f491_4f6c_dd1d,
+        };
+        let source = match signal {
+            Signal::Sine { frequency, phase } => Source::Tones {
+                frequencies: vec![nyquist(*frequency)?],
+                phase: phase.to_radians(),
+            },
+            Signal::MultiTone { frequencies } => Source::Tones {
+                frequencies: frequencies
+                    .iter()
+                    .map(|x| nyquist(*x))
+                    .collect::<Result<_, _>>()?,
+                phase: 0.0,
+            },
+            Signal::Sweep { start, end } => Source::Sweep {
+                start: nyquist(*start)?,
+                rate: (nyquist(*end)? / start).ln() * fs
+                    / duration.max(1) as f64,
+            },
+            Signal::WhiteNoise => Source::WhiteNoise(random),
+            Signal::PinkNoise => Source::PinkNoise {
+                random,
+                filters: vec![PinkFilter::default(); channels],
+                gain: PinkFilter::rms_gain(),
+            },
+            Signal::Impulse => Source::Impulse,
+            Signal::Silence => Source::Silence,
+            Signal::Ebu3341 { .. } | Signal::Ebu3342 { .. } => {
+                return Err(Error::InvalidArgument(
+                    "EBU test signals define their own parameters.".into(),
+                ))
+            }
+        };
+        if channels == 0 {
+            return Err(Error::InvalidArgument(
+                "At least one channel is required.".into(),
+            ));
+        }
+
+        Ok(Self {
+            fs,
+            channels,
+            source,
+            segments: vec![Segment {
+                end: duration,
+                gain: vec![10.0_f64.powf(level_db / 20.0); channels],
+            }],
+            index: 0,
+            segment: 0,
+        })
+    }
+
+    /// Constructs a 1 kHz sine sequence from levels in dBFS of each channel
+    /// and durations in seconds.
+    pub fn sequence(fs: f64, sequence: &[(Vec<f64>, f64)]) -> Self {
+        let mut end = 0.0;
+        let segments: Vec<Segment> = sequence
+            .iter()
+            .map(|(levels, duration)| {
+                end += duration;
+                Segment {
+                    end: (end * fs).round() as usize,
+                    gain: levels
+                        .iter()
+                        .map(|x| 10.0_f64.powf(x / 20.0))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Self {
+            fs,
+            channels: sequence[0].0.len(),
+            source: Source::Tones {
+                frequencies: vec![1000.0],
+                phase: 0.0,
+            },
+            segments,
+            index: 0,
+            segment: 0,
+        }
+    }
+
+    /// EBU Tech 3341 loudness test signal, the expected integrative
+    /// loudness is -23 LUFS except case 2 with -33 LUFS. Case 9 has a
+    /// constant short-term loudness of -23 LUFS.
+    pub fn ebu3341(case: u8, fs: f64) -> Result<Self, Error> {
+        let stereo = |x: &[(f64, f64)]| -> Vec<(Vec<f64>, f64)> {
+            x.iter().map(|(l, d)| (vec![*l; 2], *d)).collect()
+        };
+        let sequence = match case {
+            1 => stereo(&[(-23.0, 20.0)]),
+            2 => stereo(&[(-33.0, 20.0)]),
+            3 => stereo(&[(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)]),
+            4 => stereo(&[
+                (-72.0, 10.0),
+                (-36.0, 10.0),
+                (-23.0, 60.0),
+                (-36.0, 10.0),
+                (-72.0, 10.0),
+            ]),
+            5 => stereo(&[(-26.0, 20.0), (-20.0, 20.1), (-26.0, 20.0)]),
+            // L, R, C, Ls, Rs
+            6 => vec![(vec![-28.0, -28.0, -24.0, -30.0, -30.0], 20.0)],
+            9 => stereo(&[(-20.0, 1.34), (-30.0, 1.66)].repeat(20)),
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "EBU Tech 3341 case {} is not available.",
+                    case
+                )))
+            }
+        };
+
+        Ok(Self::sequence(fs, &sequence))
+    }
+
+    /// EBU Tech 3342 loudness range test signal, the expected loudness
+    /// range is 10, 5, 20 and 15 LU for case 1 to 4.
+    pub fn ebu3342(case: u8, fs: f64) -> Result<Self, Error> {
+        let levels: &[f64] = match case {
+            1 => &[-20.0, -30.0],
+            2 => &[-20.0, -15.0],
+            3 => &[-40.0, -20.0],
+            4 => &[-50.0, -35.0, -20.0, -35.0, -50.0],
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "EBU Tech 3342 case {} is not available.",
+                    case
+                )))
+            }
+        };
+        let sequence: Vec<(Vec<f64>, f64)> =
+            levels.iter().map(|x| (vec![*x; 2], 20.0)).collect();
+
+        Ok(Self::sequence(fs, &sequence))
+    }
+
+    /// Total length in samples
+    pub fn len(&self) -> usize {
+        self.segments.last().map_or(0, |x| x.end)
+    }
+
+    /// Output spec with 24 bit integer samples
+    pub fn spec(&self) -> WavSpec {
+        WavSpec {
+            channels: self.channels as u16,
+            sample_rate: self.fs as u32,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        }
+    }
+
+    /// Writes the whole signal to the output.
+    pub fn write<W>(&mut self, output: &mut FrameWriter<W>) -> Result<(), Error>
+    where
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut progress = Progress::new(self.len(), "Generating sample");
+        for frame in self {
+            progress.next();
+            output.write_frame(&frame)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the whole signal as in-memory 32 bit float wav file for
+    /// testing analyzers and operations.
+    #[cfg(test)]
+    pub fn into_wav(self) -> hound::WavReader<std::io::Cursor<Vec<u8>>> {
+        let spec = WavSpec {
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+            ..self.spec()
+        };
+        let mut wav = std::io::Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+        for frame in self {
+            for x in frame {
+                writer.write_sample(x).unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        wav.set_position(0);
+
+        hound::WavReader::new(wav).unwrap()
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.segments.get(self.segment)?.end <= self.index {
+            self.segment += 1;
+        }
+
+        let (fs, index) = (self.fs, self.index);
+        let gain = &self.segments[self.segment].gain;
+        let frame = (0..self.channels)
+            .map(|ch| (gain[ch] * self.source.sample(fs, ch, index)) as f32)
+            .collect();
+        self.index += 1;
+
+        Some(frame)
+    }
+}
+
+#[test]
+fn test_generator() {
+    let fs = 48000.0;
+    let rms = |signal: &Signal| {
+        let generator = Generator::new(signal, fs, 2, -20.0, 48000).unwrap();
+        let frames: Vec<Vec<f32>> = generator.collect();
+        assert_eq!(frames.len(), 48000);
+        let energy: f64 = frames.iter().map(|x| (x[0] as f64).powi(2)).sum();
+        let peak = frames.iter().fold(0.0_f32, |a, x| a.max(x[0].abs()));
+        (
+            10.0 * (energy / frames.len() as f64).log10(),
+            20.0 * peak.log10(),
+        )
+    };
+
+    // Sine and sweep peak levels with 3 dB crest factor.
+    for signal in [
+        Signal::Sine {
+            frequency: 1000.0,
+            phase: 0.0,
+        },
+        Signal::Sweep {
+            start: 20.0,
+            end: 20000.0,
+        },
+    ] {
+        let (rms, peak) = rms(&signal);
+        assert!((peak + 20.0).abs() < 0.01);
+        assert!((rms + 23.01).abs() < 0.1);
+    }
+
+    // Noise RMS levels
+    for signal in [Signal::WhiteNoise, Signal::PinkNoise] {
+        assert!((rms(&signal).0 + 20.0).abs() < 0.2);
+    }
+
+    assert_eq!(Generator::ebu3341(5, fs).unwrap().len(), 60 * 48000 + 4800);
+    assert_eq!(Generator::ebu3341(6, fs).unwrap().spec().channels, 5);
+    assert!(Generator::ebu3342(5, fs).is_err());
+    let wav = Generator::ebu3342(1, fs).unwrap().into_wav();
+    assert_eq!(wav.duration(), 40 * 48000);
+}
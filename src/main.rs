@@ -18,17 +18,24 @@
 #![forbid(unsafe_code)]
 
 use clap::{Parser, Subcommand};
-use hound::{WavReader, WavWriter};
+use conversion::FrameWriter;
+use frame::ChannelMap;
+use hound::{WavReader, WavSpec, WavWriter};
+use report::{Format, Report};
 
 mod analyzer;
+mod codec;
 mod conversion;
 mod effects;
+mod envelope;
 mod error;
 mod filters;
 mod frame;
+mod generator;
 mod gui;
 mod operations;
 mod progress;
+mod report;
 
 #[derive(Debug, Parser)]
 #[command(name = "audio-effects")]
@@ -36,12 +43,31 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// Input wav filename
+    /// Input wav or flac filename
     #[arg(short)]
     input_filename: Option<String>,
-    /// Output wav filename
-    #[arg(short)]
-    output_filename: Option<String>,
+    /// Speaker positions of the input channels for loudness weighting,
+    /// e.g. left,right,center,lfe,rear-left,rear-right. Overrides the
+    /// channel mask of the input file.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    channel_layout: Vec<ChannelMap>,
+    #[command(flatten)]
+    output: OutputSettings,
+    /// Report and time series output format
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, clap::Args)]
+struct OutputSettings {
+    /// Output wav or flac filename
+    #[arg(short = 'o')]
+    filename: Option<String>,
+    /// Output file format, selected by file extension if omitted
+    #[arg(long = "output-format", value_enum)]
+    file_format: Option<codec::Format>,
+    #[command(flatten)]
+    conversion: conversion::Settings,
 }
 
 #[derive(Debug, Subcommand)]
@@ -50,20 +76,58 @@ enum Commands {
     Amplify(effects::amplify::Settings),
     /// Dynamic compression
     Compressor(effects::compressor::Settings),
+    /// Apply multiple effects in one pass
+    Chain(effects::chain::Settings),
+    /// Apply an effect chain from a preset file
+    Preset(effects::chain::Preset),
+    /// Gain envelope from a file
+    Envelope(effects::envelope::Settings),
+    /// Parametric equalizer
+    Equalizer(effects::equalizer::Settings),
+    /// Fade-in and fade-out
+    Fade(effects::fade::Settings),
+    /// Noise gate and downward expander
+    Gate(effects::gate::Settings),
+    /// True peak brickwall limiter
+    Limiter(effects::limiter::Settings),
+    /// Mid/side encoder or decoder
+    MidSide(effects::mid_side::Settings),
+    /// Multiband compressor
+    Multiband(effects::multiband::Settings),
+    /// Stereo width control
+    Width(effects::width::Settings),
     /// Normalize audio loudness
     Normalize(operations::normalize::Settings),
+    /// Convert the sample rate
+    Resample(operations::resample::Settings),
+    /// Append a file with a crossfade
+    Crossfade(operations::crossfade::Settings),
+    /// Select, downmix, split or merge channels
+    Channels(operations::channels::Settings),
     /// Analyze audio true peak
     TruePeak(analyzer::true_peak::Settings),
     /// Analyze audio loudness
     Loudness(analyzer::loudness::Settings),
     /// Analyze audio RMS
     Rms(analyzer::rms::Settings),
+    /// Generate a test signal
+    Generate(generator::Settings),
+    /// Find silent regions and print them as cue list
+    Silence(analyzer::silence::Settings),
+    /// Remove leading and trailing silence
+    Trim(operations::silence::Settings),
+    /// Split into multiple files at silent regions
+    Split(operations::silence::Settings),
+    /// Analyze loudness, RMS or true peak over time
+    TimeSeries(analyzer::time_series::Settings),
+    /// Analyze the averaged power spectrum
+    Spectrum(analyzer::spectrum::Settings),
+    /// Render a spectrogram to a PNG image
+    Spectrogram(analyzer::spectrogram::Settings),
 }
 
-fn open_input(
-    input_filename: Option<String>,
-) -> WavReader<std::io::BufReader<std::fs::File>> {
-    let input = WavReader::open(input_filename.unwrap()).unwrap();
+fn open_input(input_filename: Option<String>) -> WavReader<codec::Input> {
+    let input = codec::open(&input_filename.unwrap()).unwrap();
     let spec = input.spec();
     let duration = input.duration();
     eprintln!(
@@ -74,6 +138,270 @@ fn open_input(
     input
 }
 
+/// Returns the speaker positions of the input channels from the override,
+/// the channel mask of the input file or the default layout.
+fn channel_layout(
+    input_filename: &Option<String>,
+    layout: &[ChannelMap],
+    channels: u16,
+) -> Vec<ChannelMap> {
+    if !layout.is_empty() {
+        return layout.to_vec();
+    }
+
+    let channels = channels as usize;
+    let mask = input_filename
+        .as_ref()
+        .and_then(|x| codec::channel_mask(x).ok())
+        .flatten();
+    match mask.map(|x| ChannelMap::from_mask(x, channels)) {
+        Some(Some(layout)) => layout,
+        Some(None) => {
+            eprintln!("Channel mask does not match channels, using default.");
+            ChannelMap::default_layout(channels)
+        }
+        None => ChannelMap::default_layout(channels),
+    }
+}
+
+/// Creates the output file, writes it with the given operation and
+/// finalizes it. Errors are printed and yield None.
+fn write_output<T, F>(
+    output: &OutputSettings,
+    spec: WavSpec,
+    operation: &str,
+    f: F,
+) -> Option<T>
+where
+    F: FnOnce(&mut FrameWriter<&mut codec::Output>) -> Result<T, error::Error>,
+{
+    let filename = match &output.filename {
+        Some(filename) => filename.clone(),
+        None => {
+            eprintln!("No output filename was given!");
+            return None;
+        }
+    };
+    write_outputs(output, &[filename], spec, operation, |writers| {
+        f(&mut writers[0])
+    })
+}
+
+/// Creates multiple output files with the same format, writes them with
+/// the given operation and finalizes them. Errors are printed and yield
+/// None.
+fn write_outputs<T, F>(
+    output: &OutputSettings,
+    filenames: &[String],
+    spec: WavSpec,
+    operation: &str,
+    f: F,
+) -> Option<T>
+where
+    F: FnOnce(
+        &mut [FrameWriter<&mut codec::Output>],
+    ) -> Result<T, error::Error>,
+{
+    let format = output.file_format.unwrap_or_else(|| {
+        codec::Format::from_filename(filenames.first().map_or("", |x| x))
+    });
+    let input = spec;
+    let spec = match output.conversion.spec(input, format) {
+        Ok(spec) => spec,
+        Err(e) => {
+            eprintln!("Invalid output format: {}", e);
+            return None;
+        }
+    };
+    let mut sinks = Vec::with_capacity(filenames.len());
+    for filename in filenames {
+        match codec::Output::create(filename, format, spec) {
+            Ok(sink) => sinks.push(sink),
+            Err(e) => {
+                eprintln!("Creating output file failed: {}", e);
+                return None;
+            }
+        }
+    }
+    let mut writers: Vec<_> = sinks
+        .iter_mut()
+        .map(|sink| {
+            let writer = WavWriter::new(sink, spec).unwrap();
+            FrameWriter::new(writer, output.conversion.dither(input, spec))
+        })
+        .collect();
+    let result = match f(&mut writers) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("\n{} failed: {}", operation, e);
+            return None;
+        }
+    };
+    let mut clipped = 0;
+    for writer in writers {
+        match writer.finalize() {
+            Ok(x) => clipped += x,
+            Err(e) => {
+                eprintln!("Finalizing wav file failed: {}", e);
+                return None;
+            }
+        }
+    }
+    if clipped > 0 {
+        eprintln!("\n{} samples were clipped!", clipped);
+    }
+    for sink in sinks {
+        if let Err(e) = sink.finish() {
+            eprintln!("Writing output file failed: {}", e);
+            return None;
+        }
+    }
+
+    Some(result)
+}
+
+fn print_report(report: &Report, format: Format) {
+    if let Err(e) = report.write(format, &mut std::io::stdout().lock()) {
+        eprintln!("Writing report failed: {}", e);
+    }
+}
+
+fn run_chain(
+    chain: effects::chain::Settings,
+    input_filename: Option<String>,
+    layout: &[ChannelMap],
+    output: OutputSettings,
+) {
+    let filename = input_filename.clone();
+    let mut input = open_input(input_filename);
+    let mut spec = input.spec();
+    let layout = channel_layout(&filename, layout, spec.channels);
+    spec.channels =
+        match chain.validate(spec.sample_rate as f64, spec.channels as usize) {
+            Ok(channels) => channels as u16,
+            Err(e) => {
+                eprintln!("Building effect chain failed: {}", e);
+                return;
+            }
+        };
+    write_output(&output, spec, "Processing", |output| {
+        chain.process(&mut input, output, &layout)
+    });
+}
+
+fn run_channels(
+    x: operations::channels::Settings,
+    input_filename: Option<String>,
+    layout: &[ChannelMap],
+    output: OutputSettings,
+) {
+    use effects::Effect;
+    use operations::channels::{self, Mode};
+
+    match x.mode() {
+        Mode::Merge { inputs } => {
+            let mut readers = Vec::with_capacity(inputs.len());
+            for filename in inputs {
+                match codec::open(filename) {
+                    Ok(reader) => readers.push(reader),
+                    Err(e) => {
+                        eprintln!("Opening {} failed: {}", filename, e);
+                        return;
+                    }
+                }
+            }
+            let mut spec = readers[0].spec();
+            spec.channels = readers.iter().map(|x| x.spec().channels).sum();
+            write_output(&output, spec, "Merging", |output| {
+                channels::merge(&mut readers, output)
+            });
+        }
+        Mode::Split => {
+            let mut input = open_input(input_filename);
+            let mut spec = input.spec();
+            let filenames = match &output.filename {
+                Some(x) => codec::numbered_filenames(x, spec.channels as usize),
+                None => {
+                    eprintln!("No output filename was given!");
+                    return;
+                }
+            };
+            spec.channels = 1;
+            write_outputs(&output, &filenames, spec, "Splitting", |outputs| {
+                channels::split(&mut input, outputs)
+            });
+        }
+        _ => {
+            let filename = input_filename.clone();
+            let mut input = open_input(input_filename);
+            let mut spec = input.spec();
+            let layout = channel_layout(&filename, layout, spec.channels);
+            let mut matrix = match x.build(&layout) {
+                Ok(matrix) => matrix,
+                Err(e) => {
+                    eprintln!("Invalid channel selection: {}", e);
+                    return;
+                }
+            };
+            spec.channels = matrix.output_channels() as u16;
+            write_output(&output, spec, "Mixing", |output| {
+                effects::apply(&mut matrix, &mut input, output, "Mixing sample")
+            });
+        }
+    }
+}
+
+fn run_silence(
+    x: operations::silence::Settings,
+    input_filename: Option<String>,
+    layout: &[ChannelMap],
+    output: OutputSettings,
+    format: Format,
+    split: bool,
+) {
+    use operations::silence::Settings;
+
+    let filename = input_filename.clone();
+    let mut input = open_input(input_filename);
+    let spec = input.spec();
+    let layout = channel_layout(&filename, layout, spec.channels);
+    let regions = if split {
+        x.split_regions(&mut input, &layout, format)
+    } else {
+        x.trim_region(&mut input, &layout, format)
+    };
+    let regions = match regions {
+        Ok(regions) if regions.is_empty() => {
+            eprintln!("Input is silent.");
+            return;
+        }
+        Ok(regions) => regions,
+        Err(e) => {
+            eprintln!("Silence analysis failed: {}", e);
+            return;
+        }
+    };
+
+    let filenames = match (&output.filename, split) {
+        (Some(x), true) => codec::numbered_filenames(x, regions.len()),
+        (Some(x), false) => vec![x.clone()],
+        (None, _) => {
+            eprintln!("No output filename was given!");
+            return;
+        }
+    };
+    // One file after the other, so only one output is open at a time.
+    for (region, filename) in regions.iter().zip(filenames) {
+        let written =
+            write_outputs(&output, &[filename], spec, "Writing", |outputs| {
+                Settings::write(&mut input, *region, &mut outputs[0])
+            });
+        if written.is_none() {
+            return;
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -82,95 +410,381 @@ fn main() {
         Some(x) => match x {
             Commands::Amplify(x) => {
                 let mut input = open_input(cli.input_filename);
-                let mut output = match &cli.output_filename {
-                    Some(filename) => {
-                        WavWriter::create(filename, input.spec()).unwrap()
-                    }
-                    None => {
-                        println!("No output filename was given!");
-                        return;
-                    }
-                };
-                if let Err(e) = x.amplify(&mut input, &mut output) {
-                    println!("\nAmplifying failed: {}", e.to_string());
-                }
-                if let Err(e) = output.finalize() {
-                    println!("Finalizing wav file failed: {}", e.to_string());
-                }
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Amplifying",
+                    |output| x.amplify(&mut input, output),
+                );
             }
             Commands::Compressor(x) => {
                 let mut input = open_input(cli.input_filename);
-                let mut output = match &cli.output_filename {
-                    Some(filename) => {
-                        WavWriter::create(filename, input.spec()).unwrap()
-                    }
-                    None => {
-                        println!("No output filename was given!");
-                        return;
-                    }
-                };
-                if let Err(e) = x.compress(&mut input, &mut output) {
-                    println!("\nCompressing failed: {}", e.to_string());
-                }
-                if let Err(e) = output.finalize() {
-                    println!("Finalizing wav file failed: {}", e.to_string());
-                }
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Compressing",
+                    |output| x.compress(&mut input, output),
+                );
+            }
+            Commands::Chain(x) => run_chain(
+                x,
+                cli.input_filename,
+                &cli.channel_layout,
+                cli.output,
+            ),
+            Commands::Preset(x) => match x.load() {
+                Ok(x) => run_chain(
+                    x,
+                    cli.input_filename,
+                    &cli.channel_layout,
+                    cli.output,
+                ),
+                Err(e) => eprintln!("Loading preset failed: {}", e),
+            },
+            Commands::Envelope(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Processing",
+                    |output| x.apply(&mut input, output),
+                );
+            }
+            Commands::Fade(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(&cli.output, input.spec(), "Fading", |output| {
+                    x.fade(&mut input, output)
+                });
+            }
+            Commands::Crossfade(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Crossfading",
+                    |output| x.crossfade(&mut input, output),
+                );
+            }
+            Commands::Equalizer(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Equalizing",
+                    |output| x.equalize(&mut input, output),
+                );
+            }
+            Commands::Gate(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(&cli.output, input.spec(), "Gating", |output| {
+                    x.gate(&mut input, output)
+                });
+            }
+            Commands::Limiter(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(&cli.output, input.spec(), "Limiting", |output| {
+                    x.limit(&mut input, output)
+                });
+            }
+            Commands::MidSide(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Converting",
+                    |output| x.convert(&mut input, output),
+                );
+            }
+            Commands::Multiband(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Compressing",
+                    |output| x.compress(&mut input, output),
+                );
+            }
+            Commands::Width(x) => {
+                let mut input = open_input(cli.input_filename);
+                write_output(&cli.output, input.spec(), "Widening", |output| {
+                    x.widen(&mut input, output)
+                });
             }
             Commands::Normalize(x) => {
+                let filename = cli.input_filename.clone().unwrap_or_default();
                 let mut input = open_input(cli.input_filename);
-                let mut output = match &cli.output_filename {
-                    Some(filename) => {
-                        WavWriter::create(filename, input.spec()).unwrap()
+                let mut report = Report::new(&filename, &input);
+                let layout = channel_layout(
+                    &Some(filename),
+                    &cli.channel_layout,
+                    input.spec().channels,
+                );
+                let gain = match write_output(
+                    &cli.output,
+                    input.spec(),
+                    "Normalizing",
+                    |output| x.normalize(&mut input, output, &layout),
+                ) {
+                    Some(gain) => gain,
+                    None => return,
+                };
+                report.push("gain", "dB", gain);
+
+                // Verify the result by measuring the written output.
+                let mut output = open_input(cli.output.filename);
+                match x.measure(&mut output, &layout) {
+                    Ok((loudness, true_peak)) => {
+                        report.push_channels(
+                            "integrative-loudness",
+                            "LUFS",
+                            loudness
+                                .iter()
+                                .map(|x| x.channel.clone())
+                                .collect(),
+                            loudness
+                                .iter()
+                                .map(|x| 10.0 * x.integrative.log10())
+                                .collect(),
+                        );
+                        report.push(
+                            "true-peak",
+                            "dBTP",
+                            true_peak
+                                .iter()
+                                .map(|x| 20.0 * x.log10())
+                                .collect(),
+                        );
+                        print_report(&report, cli.format);
                     }
-                    None => {
-                        println!("No output filename was given!");
-                        return;
+                    Err(e) => {
+                        eprintln!("Output analysis failed: {}", e)
                     }
-                };
-                if let Err(e) = x.normalize(&mut input, &mut output) {
-                    println!("\nNormalizing failed: {}", e.to_string());
-                }
-                if let Err(e) = output.finalize() {
-                    println!("Finalizing wav file failed: {}", e.to_string());
                 }
             }
+            Commands::Resample(x) => {
+                let mut input = open_input(cli.input_filename);
+                let mut spec = input.spec();
+                spec.sample_rate = x.sample_rate();
+                write_output(&cli.output, spec, "Resampling", |output| {
+                    x.resample(&mut input, output)
+                });
+            }
+            Commands::Channels(x) => run_channels(
+                x,
+                cli.input_filename,
+                &cli.channel_layout,
+                cli.output,
+            ),
             Commands::TruePeak(x) => {
-                match x.analyze(&mut open_input(cli.input_filename)) {
+                let filename = cli.input_filename.clone().unwrap_or_default();
+                let mut input = open_input(cli.input_filename);
+                let mut report = Report::new(&filename, &input);
+                match x.analyze(&mut input) {
                     Ok(true_peak) => {
-                        println!("Input has true peak at {:?}", true_peak)
+                        report.push(
+                            "true-peak",
+                            "dBTP",
+                            true_peak
+                                .iter()
+                                .map(|x| 20.0 * x.log10())
+                                .collect(),
+                        );
+                        print_report(&report, cli.format);
                     }
                     Err(e) => {
-                        println!("True peak analyses failed: {}", e.to_string())
+                        eprintln!("True peak analyses failed: {}", e)
                     }
                 }
             }
             Commands::Loudness(x) => {
-                match x.analyze(&mut open_input(cli.input_filename)) {
-                    Ok(loudness) => println!(
-                        "Input has integrative loudness of {:?} LUFS",
-                        loudness
+                let filename = cli.input_filename.clone().unwrap_or_default();
+                let mut input = open_input(cli.input_filename);
+                let mut report = Report::new(&filename, &input);
+                let layout = channel_layout(
+                    &Some(filename),
+                    &cli.channel_layout,
+                    input.spec().channels,
+                );
+                match x.analyze_statistics(&mut input, &layout) {
+                    Ok(loudness) => {
+                        let channels: Vec<String> = loudness
                             .iter()
-                            .map(|x| 10.0 * x.log10())
-                            .collect::<Vec<f64>>(),
-                    ),
+                            .map(|x| x.channel.clone())
+                            .collect();
+                        let lufs =
+                            |f: fn(&analyzer::loudness::Statistics) -> f64| {
+                                loudness
+                                    .iter()
+                                    .map(|x| 10.0 * f(x).log10())
+                                    .collect()
+                            };
+                        report.push_channels(
+                            "integrative-loudness",
+                            "LUFS",
+                            channels.clone(),
+                            lufs(|x| x.integrative),
+                        );
+                        report.push_channels(
+                            "momentary-max",
+                            "LUFS",
+                            channels.clone(),
+                            lufs(|x| x.momentary_max),
+                        );
+                        report.push_channels(
+                            "short-term-max",
+                            "LUFS",
+                            channels.clone(),
+                            lufs(|x| x.short_term_max),
+                        );
+                        report.push_channels(
+                            "loudness-range",
+                            "LU",
+                            channels,
+                            loudness.iter().map(|x| x.range).collect(),
+                        );
+                        print_report(&report, cli.format);
+                    }
                     Err(e) => {
-                        println!("Loudness analysis failed: {}", e.to_string())
+                        eprintln!("Loudness analysis failed: {}", e)
                     }
                 }
             }
             Commands::Rms(x) => {
-                match x.analyze(&mut open_input(cli.input_filename)) {
-                    Ok(rms) => println!(
-                        "Input has RMS of {:?} dB",
-                        rms.iter()
-                            .map(|x| 20.0 * x.log10())
-                            .collect::<Vec<f64>>()
-                    ),
+                let filename = cli.input_filename.clone().unwrap_or_default();
+                let mut input = open_input(cli.input_filename);
+                let mut report = Report::new(&filename, &input);
+                match x.analyze(&mut input) {
+                    Ok(rms) => {
+                        report.push(
+                            "rms",
+                            "dBFS",
+                            rms.iter().map(|x| 20.0 * x.log10()).collect(),
+                        );
+                        print_report(&report, cli.format);
+                    }
                     Err(e) => {
-                        println!("RMS analysis failed: {}", e.to_string())
+                        eprintln!("RMS analysis failed: {}", e)
                     }
                 }
             }
+            Commands::Generate(x) => match x.build() {
+                Ok(mut generator) => {
+                    write_output(
+                        &cli.output,
+                        generator.spec(),
+                        "Generating",
+                        |output| generator.write(output),
+                    );
+                }
+                Err(e) => eprintln!("Invalid test signal: {}", e),
+            },
+            Commands::Silence(x) => {
+                let filename = cli.input_filename.clone();
+                let mut input = open_input(cli.input_filename);
+                let spec = input.spec();
+                let layout = channel_layout(
+                    &filename,
+                    &cli.channel_layout,
+                    spec.channels,
+                );
+                let result =
+                    x.analyze(&mut input, &layout).and_then(|regions| {
+                        analyzer::silence::Settings::write(
+                            &regions,
+                            spec.sample_rate as f64,
+                            cli.format,
+                            &mut std::io::stdout().lock(),
+                        )
+                    });
+                if let Err(e) = result {
+                    eprintln!("\nSilence analysis failed: {}", e);
+                }
+            }
+            Commands::Trim(x) => run_silence(
+                x,
+                cli.input_filename,
+                &cli.channel_layout,
+                cli.output,
+                cli.format,
+                false,
+            ),
+            Commands::Split(x) => run_silence(
+                x,
+                cli.input_filename,
+                &cli.channel_layout,
+                cli.output,
+                cli.format,
+                true,
+            ),
+            Commands::TimeSeries(x) => {
+                let filename = cli.input_filename.clone();
+                let mut input = open_input(cli.input_filename);
+                let layout = channel_layout(
+                    &filename,
+                    &cli.channel_layout,
+                    input.spec().channels,
+                );
+                let mut output = match &cli.output.filename {
+                    Some(filename) => match std::fs::File::create(filename) {
+                        Ok(x) => std::io::BufWriter::new(x),
+                        Err(e) => {
+                            eprintln!("Creating output file failed: {}", e);
+                            return;
+                        }
+                    },
+                    None => {
+                        eprintln!("No output filename was given!");
+                        return;
+                    }
+                };
+                let result =
+                    x.analyze(&mut input, &layout).and_then(|series| {
+                        x.write(&series, cli.format, &mut output)
+                    });
+                if let Err(e) = result {
+                    eprintln!("\nTime series analysis failed: {}", e);
+                }
+            }
+            Commands::Spectrum(x) => {
+                let mut input = open_input(cli.input_filename);
+                let mut output: Box<dyn std::io::Write> =
+                    match &cli.output.filename {
+                        Some(filename) => match std::fs::File::create(filename)
+                        {
+                            Ok(x) => Box::new(std::io::BufWriter::new(x)),
+                            Err(e) => {
+                                eprintln!("Creating output file failed: {}", e);
+                                return;
+                            }
+                        },
+                        None => Box::new(std::io::stdout().lock()),
+                    };
+                let result = x.analyze(&mut input).and_then(|spectrum| {
+                    x.write(&spectrum, cli.format, &mut output)
+                });
+                if let Err(e) = result {
+                    eprintln!("\nSpectrum analysis failed: {}", e);
+                }
+            }
+            Commands::Spectrogram(x) => {
+                let mut input = open_input(cli.input_filename);
+                let output = match &cli.output.filename {
+                    Some(filename) => match std::fs::File::create(filename) {
+                        Ok(x) => std::io::BufWriter::new(x),
+                        Err(e) => {
+                            eprintln!("Creating output file failed: {}", e);
+                            return;
+                        }
+                    },
+                    None => {
+                        eprintln!("No output filename was given!");
+                        return;
+                    }
+                };
+                if let Err(e) = x.render(&mut input, output) {
+                    eprintln!("\nSpectrogram rendering failed: {}", e);
+                }
+            }
         },
     };
 }
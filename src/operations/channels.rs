@@ -0,0 +1,319 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::conversion::{Conversion, FrameWriter};
+use crate::effects::Effect;
+use crate::error::Error;
+use crate::frame::{ChannelMap, FrameIterator};
+use crate::progress::Progress;
+use clap::ValueEnum;
+use hound::WavReader;
+
+/// Channel given by 1-based index or by speaker position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Index(usize),
+    Position(ChannelMap),
+}
+
+impl std::str::FromStr for Channel {
+    type Err = String;
+
+    /// Parses a channel from an index like "2" or a position like "left".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(0) => Err("Channel indices start at 1.".into()),
+            Ok(x) => Ok(Self::Index(x)),
+            Err(_) => ChannelMap::from_str(s, true).map(Self::Position),
+        }
+    }
+}
+
+impl Channel {
+    /// Returns the 0-based index of this channel in the layout.
+    fn resolve(&self, layout: &[ChannelMap]) -> Result<usize, Error> {
+        match self {
+            Self::Index(x) if *x <= layout.len() => Ok(x - 1),
+            Self::Position(x) => {
+                layout.iter().position(|y| x == y).ok_or_else(|| {
+                    Error::InvalidArgument(format!(
+                        "Input has no {:?} channel.",
+                        x
+                    ))
+                })
+            }
+            Self::Index(x) => Err(Error::InvalidArgument(format!(
+                "Input has no channel {}.",
+                x
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Mode {
+    /// Select and reorder channels by 1-based index or speaker position,
+    /// e.g. "2 1" or "right left"
+    Select {
+        #[arg(required = true)]
+        channels: Vec<Channel>,
+    },
+    /// Downmix 5.1 to stereo with ITU-R BS.775 coefficients
+    Downmix,
+    /// Sum all channels except the LFE to mono
+    Mono,
+    /// Split into mono files which are named after the output file
+    /// with the channel number as suffix
+    Split,
+    /// Merge the given files into one interleaved file, the input file
+    /// is not used
+    Merge {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    #[command(subcommand)]
+    mode: Mode,
+}
+
+impl Settings {
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// Constructs the mixing matrix of the select, downmix and mono modes
+    /// for inputs with the given speaker positions.
+    pub fn build(&self, layout: &[ChannelMap]) -> Result<Matrix, Error> {
+        let channels = layout.len();
+        let gains = match &self.mode {
+            Mode::Select { channels: selected } => selected
+                .iter()
+                .map(|x| {
+                    let index = x.resolve(layout)?;
+                    let mut gains = vec![0.0; channels];
+                    gains[index] = 1.0;
+                    Ok(gains)
+                })
+                .collect::<Result<Vec<Vec<f32>>, Error>>()?,
+            Mode::Downmix => {
+                let k = std::f32::consts::FRAC_1_SQRT_2;
+                let (left, right) = layout
+                    .iter()
+                    .map(|x| match x {
+                        ChannelMap::Left => Ok((1.0, 0.0)),
+                        ChannelMap::Right => Ok((0.0, 1.0)),
+                        ChannelMap::Center => Ok((k, k)),
+                        ChannelMap::Lfe => Ok((0.0, 0.0)),
+                        ChannelMap::RearLeft | ChannelMap::SideLeft => {
+                            Ok((k, 0.0))
+                        }
+                        ChannelMap::RearRight | ChannelMap::SideRight => {
+                            Ok((0.0, k))
+                        }
+                        x => Err(Error::InvalidArgument(format!(
+                            "{:?} channel can not be downmixed.",
+                            x
+                        ))),
+                    })
+                    .collect::<Result<(Vec<f32>, Vec<f32>), Error>>()?;
+                vec![left, right]
+            }
+            Mode::Mono => {
+                let mixed = layout
+                    .iter()
+                    .filter(|x| !matches!(x, ChannelMap::Lfe))
+                    .count();
+                if mixed == 0 {
+                    return Err(Error::InvalidArgument(
+                        "LFE channel can not be mixed to mono.".into(),
+                    ));
+                }
+                let gain = 1.0 / mixed as f32;
+                vec![layout
+                    .iter()
+                    .map(|x| match x {
+                        ChannelMap::Lfe => 0.0,
+                        _ => gain,
+                    })
+                    .collect()]
+            }
+            Mode::Split | Mode::Merge { .. } => {
+                return Err(Error::InvalidArgument(
+                    "Split and merge do not mix channels.".into(),
+                ))
+            }
+        };
+
+        Ok(Matrix { channels, gains })
+    }
+}
+
+/// Writes every input channel to its own mono output.
+pub fn split<R, W>(
+    input: &mut WavReader<R>,
+    outputs: &mut [FrameWriter<W>],
+) -> Result<(), Error>
+where
+    R: std::io::Read,
+    W: std::io::Write + std::io::Seek,
+{
+    let spec = input.spec();
+    if outputs.len() != spec.channels as usize {
+        return Err(Error::InvalidArgument(
+            "Split requires one output per channel.".into(),
+        ));
+    }
+
+    let mut progress =
+        Progress::new(input.duration() as usize, "Splitting sample");
+    let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+    while let Some(frame) = frames.next() {
+        progress.next();
+        match frame {
+            Ok(frame) => {
+                for (x, output) in frame.iter().zip(outputs.iter_mut()) {
+                    output.write_frame(&[*x])?;
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Interleaves the channels of all inputs in the given order. Shorter
+/// inputs are padded with silence.
+pub fn merge<R, W>(
+    inputs: &mut [WavReader<R>],
+    output: &mut FrameWriter<W>,
+) -> Result<(), Error>
+where
+    R: std::io::Read,
+    W: std::io::Write + std::io::Seek,
+{
+    let sample_rate = inputs.first().map(|x| x.spec().sample_rate);
+    if inputs
+        .iter()
+        .any(|x| Some(x.spec().sample_rate) != sample_rate)
+    {
+        return Err(Error::InvalidArgument(
+            "All inputs must have the same sample rate.".into(),
+        ));
+    }
+
+    let duration = inputs.iter().map(|x| x.duration()).max().unwrap_or(0);
+    let channels: Vec<u16> = inputs.iter().map(|x| x.spec().channels).collect();
+    let mut frames: Vec<_> = inputs
+        .iter_mut()
+        .zip(channels.iter())
+        .map(|(x, channels)| FrameIterator::new(x.samples_f32(), *channels))
+        .collect();
+
+    let mut progress = Progress::new(duration as usize, "Merging sample");
+    for _ in 0..duration {
+        progress.next();
+        let mut merged =
+            Vec::with_capacity(channels.iter().sum::<u16>() as usize);
+        for (frames, channels) in frames.iter_mut().zip(channels.iter()) {
+            match frames.next() {
+                Some(Ok(frame)) => merged.extend_from_slice(frame),
+                Some(Err(e)) => return Err(e.into()),
+                None => merged
+                    .extend(std::iter::repeat(0.0).take(*channels as usize)),
+            }
+        }
+        output.write_frame(&merged)?;
+    }
+
+    Ok(())
+}
+
+/// Mixing matrix which computes every output channel as weighted sum
+/// of the input channels.
+#[derive(Debug, Clone)]
+pub struct Matrix {
+    /// Number of input channels
+    channels: usize,
+    /// Input channel gains of every output channel
+    gains: Vec<Vec<f32>>,
+}
+
+impl Effect for Matrix {
+    fn process(&mut self, frame: &[f32]) -> Result<Vec<f32>, Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        Ok(self
+            .gains
+            .iter()
+            .map(|gains| {
+                gains.iter().zip(frame.iter()).map(|(g, x)| g * x).sum()
+            })
+            .collect())
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn reset(&mut self) {}
+
+    fn output_channels(&self) -> usize {
+        self.gains.len()
+    }
+}
+
+#[test]
+fn test_channel_matrix() {
+    use ChannelMap::*;
+
+    let layout = [Left, Right, Center, Lfe, SideLeft, SideRight];
+    let frame = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+    let mut select = Settings {
+        mode: Mode::Select {
+            channels: vec!["right".parse().unwrap(), "1".parse().unwrap()],
+        },
+    }
+    .build(&layout)
+    .unwrap();
+    assert_eq!(select.process(&frame).unwrap(), [2.0, 1.0]);
+
+    let mut downmix = Settings {
+        mode: Mode::Downmix,
+    }
+    .build(&layout)
+    .unwrap();
+    let k = std::f32::consts::FRAC_1_SQRT_2;
+    let output = downmix.process(&frame).unwrap();
+    assert!((output[0] - (1.0 + 3.0 * k + 5.0 * k)).abs() < 1e-6);
+    assert!((output[1] - (2.0 + 3.0 * k + 6.0 * k)).abs() < 1e-6);
+
+    let mut mono = Settings { mode: Mode::Mono }.build(&layout).unwrap();
+    // The LFE is not part of the mono mix.
+    assert!((mono.process(&frame).unwrap()[0] - 3.4).abs() < 1e-6);
+    assert!(Settings { mode: Mode::Mono }.build(&[Lfe]).is_err());
+
+    assert_eq!(
+        crate::codec::numbered_filenames("out.wav", 2),
+        ["out_1.wav", "out_2.wav"]
+    );
+}
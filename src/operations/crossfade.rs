@@ -0,0 +1,176 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::codec;
+use crate::conversion::{Conversion, FrameWriter};
+use crate::envelope::{Curve, Position};
+use crate::error::Error;
+use crate::frame::FrameIterator;
+use crate::progress::Progress;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// File which is appended to the input
+    filename: String,
+    /// Overlap of the input end and the appended file start in seconds
+    /// or with unit suffix s, ms or samples
+    #[arg(long, default_value = "1")]
+    duration: Position,
+    /// Fade curve, equal-power keeps the loudness of uncorrelated signals
+    /// and linear the loudness of correlated signals
+    #[arg(long, value_enum, default_value_t = Curve::EqualPower)]
+    curve: Curve,
+}
+
+impl Settings {
+    pub fn crossfade<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let mut appended = codec::open(&self.filename)?;
+        self.append(input, &mut appended, output)
+    }
+
+    /// Writes the input followed by the appended input, both overlap by
+    /// the crossfade duration.
+    fn append<R, A, W>(
+        &self,
+        input: &mut WavReader<R>,
+        appended: &mut WavReader<A>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        A: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let appended_spec = appended.spec();
+        if spec.channels != appended_spec.channels
+            || spec.sample_rate != appended_spec.sample_rate
+        {
+            return Err(Error::InvalidArgument(
+                "Both files must have the same channels and sample rate."
+                    .into(),
+            ));
+        }
+
+        let overlap = self.duration.samples(spec.sample_rate as f64);
+        let duration = input.duration() as usize;
+        if overlap > duration || overlap > appended.duration() as usize {
+            return Err(Error::InvalidArgument(
+                "Crossfade is longer than the input or appended file.".into(),
+            ));
+        }
+
+        let fade_start = duration - overlap;
+        let mut progress = Progress::new(
+            duration + appended.duration() as usize - overlap,
+            "Crossfading sample",
+        );
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        let mut appended_frames =
+            FrameIterator::new(appended.samples_f32(), spec.channels);
+        let mut index = 0;
+        while let Some(frame) = frames.next() {
+            progress.next();
+            let frame = frame?;
+            if index < fade_start {
+                output.write_frame(frame)?;
+            } else {
+                let t = (index - fade_start) as f64 / overlap as f64;
+                let fade_out = self.curve.interpolate(1.0, 0.0, t) as f32;
+                let fade_in = self.curve.interpolate(0.0, 1.0, t) as f32;
+                let mixed: Vec<f32> = match appended_frames.next() {
+                    Some(Ok(x)) => frame
+                        .iter()
+                        .zip(x.iter())
+                        .map(|(a, b)| a * fade_out + b * fade_in)
+                        .collect(),
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err(Error::InvalidFrame),
+                };
+                output.write_frame(&mixed)?;
+            }
+            index += 1;
+        }
+
+        while let Some(frame) = appended_frames.next() {
+            progress.next();
+            match frame {
+                Ok(frame) => output.write_frame(frame)?,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_crossfade() {
+    use crate::conversion::Dither;
+    use crate::generator::{Generator, Signal};
+
+    // Two sines in phase add up to the continuous sine with a linear
+    // crossfade.
+    let sine = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    let wav = |duration| {
+        Generator::new(&sine, 48000.0, 1, -6.0, duration)
+            .unwrap()
+            .into_wav()
+    };
+    let crossfade = |overlap| {
+        let settings = Settings {
+            filename: String::new(),
+            duration: Position::Samples(overlap),
+            curve: Curve::Linear,
+        };
+        let mut output = std::io::Cursor::new(Vec::new());
+        let writer = hound::WavWriter::new(&mut output, wav(0).spec()).unwrap();
+        let mut writer = FrameWriter::new(writer, Dither::None);
+        settings.append(&mut wav(4800), &mut wav(4800), &mut writer)?;
+        writer.finalize()?;
+        output.set_position(0);
+        Ok::<Vec<f32>, Error>(
+            WavReader::new(output)?
+                .samples::<f32>()
+                .map(|x| x.unwrap())
+                .collect(),
+        )
+    };
+
+    let output = crossfade(480).unwrap();
+    let expected: Vec<Vec<f32>> = Generator::new(&sine, 48000.0, 1, -6.0, 9120)
+        .unwrap()
+        .collect();
+    assert_eq!(output.len(), expected.len());
+    for (x, y) in output.iter().zip(expected.iter()) {
+        assert!((x - y[0]).abs() < 1e-6);
+    }
+
+    assert!(crossfade(4801).is_err());
+}
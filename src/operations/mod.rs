@@ -15,4 +15,8 @@
     You should have received a copy of the GNU General Public License
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
+pub mod channels;
+pub mod crossfade;
 pub mod normalize;
+pub mod resample;
+pub mod silence;
@@ -16,14 +16,24 @@
     along with this program.  If not, see <https://www.gnu.org/licenses/>.
 \******************************************************************************/
 use crate::analyzer::{
-    loudness::Settings as Lufs, rms::Settings as Rms,
-    true_peak::Settings as TruePeak,
+    loudness::{Loudness, Settings as Lufs, Statistics},
+    rms::Rms,
+    true_peak::{Settings as TruePeak, TruePeak as TruePeakAnalyzer},
+};
+use crate::conversion::FrameWriter;
+use crate::effects::{
+    self,
+    amplify::Settings as Amplify,
+    chain::Chain,
+    limiter::{Limiter, Settings as LimiterSettings},
+    Effect,
 };
-use crate::effects::amplify::Settings as Amplify;
 use crate::error::Error;
-use hound::{WavReader, WavWriter};
+use crate::frame::ChannelMap;
+use hound::WavReader;
 
-#[derive(Clone, Debug, clap::ValueEnum)]
+#[derive(Clone, Debug, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Mode {
     /// Analyze true peak amplitude
     TruePeak,
@@ -31,9 +41,12 @@ pub enum Mode {
     Lufs,
     /// Analyze RMS loudness
     Rms,
+    /// Analyze LUFS loudness and keep true peak below the ceiling
+    LufsTruePeak,
 }
 
-#[derive(Debug, Clone, clap::Args)]
+#[derive(Debug, Clone, clap::Args, serde::Deserialize)]
+#[command(allow_negative_numbers = true)]
 pub struct Settings {
     /// Algorithm to use
     mode: Mode,
@@ -41,57 +54,397 @@ pub struct Settings {
     target_db: f64,
     /// Analyze multiple channels independently
     #[arg(short)]
+    #[serde(default)]
     channel_independent: bool,
     /// Do not normalize result to stereo.
     /// You should only use this flag if you have to be strictly
     /// EBU R128 compliant.
     #[arg(short)]
+    #[serde(default)]
     strict_ebur128: bool,
+    /// True peak ceiling in dBTP for lufs-true-peak mode.
+    #[arg(long, default_value_t = default_ceiling_db())]
+    #[serde(default = "default_ceiling_db")]
+    ceiling_db: f64,
+    /// Engage a limiter instead of reducing the gain if the true peak
+    /// ceiling would be exceeded in lufs-true-peak mode.
+    #[arg(short)]
+    #[serde(default)]
+    limit: bool,
+    /// Limiter lookahead time in seconds.
+    #[arg(long, default_value_t = default_lookahead_time())]
+    #[serde(default = "default_lookahead_time")]
+    lookahead_time: f64,
+    /// Limiter release time in seconds.
+    #[arg(long, default_value_t = default_release_time())]
+    #[serde(default = "default_release_time")]
+    release_time: f64,
+}
+
+fn default_ceiling_db() -> f64 {
+    -1.0
+}
+
+fn default_lookahead_time() -> f64 {
+    0.005
+}
+
+fn default_release_time() -> f64 {
+    0.1
 }
 
 impl Settings {
+    /// Normalizes the input and returns the applied gain in dB.
+    /// The layout gives the speaker position of every input channel.
     pub fn normalize<R, W>(
         &self,
-        mut input: &mut WavReader<R>,
-        mut output: &mut WavWriter<W>,
-    ) -> Result<(), Error>
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+        layout: &[ChannelMap],
+    ) -> Result<Vec<f64>, Error>
     where
         R: std::io::Read + std::io::Seek,
         W: std::io::Write + std::io::Seek,
     {
+        let spec = input.spec();
+        let fs = spec.sample_rate as f64;
+        let channels = spec.channels as usize;
+
+        if layout.len() != channels {
+            return Err(Error::InvalidArgument(
+                "Channel layout does not match the number of channels.".into(),
+            ));
+        }
+
+        // Check the settings before the analysis pass.
+        self.build(&[0.0], fs, channels)?;
+
+        let mut measurement = self.measurement(fs, layout);
+        let mut bypass = Chain::new(Vec::new(), channels);
+        effects::apply_to(
+            &mut bypass,
+            input,
+            |frame| measurement.process(frame),
+            "Analyzing sample",
+        )?;
+        let gain_db = self.gain_db(measurement)?;
+
+        input.seek(0)?;
+        let mut chain =
+            Chain::new(self.build(&gain_db, fs, channels)?, channels);
+        effects::apply(&mut chain, input, output, "Processing sample")?;
+
+        Ok(gain_db.iter().map(|x| *x as f64).collect())
+    }
+
+    /// Constructs the level measurement required by the mode for
+    /// channels with the given speaker positions.
+    pub fn measurement(&self, fs: f64, layout: &[ChannelMap]) -> Measurement {
+        let channels = layout.len();
+        let (count, analyzer_channels) = if self.channel_independent {
+            (channels, 1)
+        } else {
+            (1, channels)
+        };
+        let (loudness, rms, true_peak) = match self.mode {
+            Mode::TruePeak => (0, 0, count),
+            Mode::Lufs => (count, 0, 0),
+            Mode::Rms => (0, count, 0),
+            Mode::LufsTruePeak => (count, 0, count),
+        };
+        let weights = ChannelMap::weights(layout);
+        let loudness_norm = if self.strict_ebur128 || self.channel_independent {
+            1.0
+        } else {
+            2.0 / channels as f64
+        };
+
+        Measurement {
+            channels,
+            channel_independent: self.channel_independent,
+            loudness_norm,
+            // Independent channels keep their BS.1770 weight, the LFE is
+            // not measured and keeps its level.
+            loudness: (0..loudness)
+                .map(|i| {
+                    if self.channel_independent {
+                        Some(weights[i])
+                            .filter(|x| *x > 0.0)
+                            .map(|x| Loudness::with_weights(fs, vec![x]))
+                    } else {
+                        Some(Loudness::with_layout(fs, layout))
+                    }
+                })
+                .collect(),
+            rms: vec![Rms::new(analyzer_channels); rms],
+            true_peak: vec![
+                TruePeakAnalyzer::new(analyzer_channels);
+                true_peak
+            ],
+        }
+    }
+
+    /// Calculates the normalization gain in dB from the measured levels.
+    pub fn gain_db(&self, measurement: Measurement) -> Result<Vec<f32>, Error> {
+        let (loudness, rms, true_peak) = measurement.finalize()?;
         let gain = match &self.mode {
-            Mode::TruePeak => {
-                let analyzer = TruePeak::new(self.channel_independent);
-                let peak = analyzer.analyze(&mut input)?;
-                peak.iter()
-                    .map(|x| (10.0_f64.powf(self.target_db / 20.0) / x) as f32)
-                    .collect::<Vec<f32>>()
-            }
-            Mode::Lufs => {
-                let analyzer =
-                    Lufs::new(self.channel_independent, self.strict_ebur128);
-                let loudness = analyzer.analyze(&mut input)?;
+            Mode::TruePeak => true_peak
+                .iter()
+                .map(|x| 10.0_f64.powf(self.target_db / 20.0) / x)
+                .collect::<Vec<f64>>(),
+            Mode::Lufs => loudness
+                .iter()
+                .map(|x| {
+                    x.map_or(1.0, |x| {
+                        (10.0_f64.powf(self.target_db / 10.0) / x).sqrt()
+                    })
+                })
+                .collect::<Vec<f64>>(),
+            Mode::Rms => rms
+                .iter()
+                .map(|x| 10.0_f64.powf(self.target_db / 20.0) / x)
+                .collect::<Vec<f64>>(),
+            Mode::LufsTruePeak => {
+                let ceiling = 10.0_f64.powf(self.ceiling_db / 20.0);
                 loudness
                     .iter()
-                    .map(|x| {
-                        (10.0_f64.powf(self.target_db / 10.0) / x).sqrt() as f32
+                    .zip(true_peak.iter())
+                    .map(|(x, peak)| {
+                        let x = match x {
+                            Some(x) => x,
+                            None => return 1.0,
+                        };
+                        let gain =
+                            (10.0_f64.powf(self.target_db / 10.0) / x).sqrt();
+                        // Reduce gain if the limiter is disabled and the
+                        // amplified true peak would exceed the ceiling.
+                        if !self.limit && gain * peak > ceiling {
+                            ceiling / peak
+                        } else {
+                            gain
+                        }
                     })
-                    .collect::<Vec<f32>>()
-            }
-            Mode::Rms => {
-                let analyzer = Rms::new(self.channel_independent);
-                let rms = analyzer.analyze(&mut input)?;
-                rms.iter()
-                    .map(|x| (10.0_f64.powf(self.target_db / 20.0) / x) as f32)
-                    .collect::<Vec<f32>>()
+                    .collect::<Vec<f64>>()
             }
         };
 
+        Ok(gain.iter().map(|x| (20.0 * x.log10()) as f32).collect())
+    }
+
+    /// Constructs the effects which apply the given gain in dB.
+    pub fn build(
+        &self,
+        gain_db: &[f32],
+        fs: f64,
+        channels: usize,
+    ) -> Result<Vec<Box<dyn Effect>>, Error> {
+        if !self.target_db.is_finite() || !self.ceiling_db.is_finite() {
+            return Err(Error::InvalidArgument(
+                "Target level and ceiling must be finite.".into(),
+            ));
+        }
+
+        let amplifier = Amplify::new(gain_db.to_vec()).build(channels)?;
+        let mut effects: Vec<Box<dyn Effect>> = vec![amplifier];
+        if let (Mode::LufsTruePeak, true) = (&self.mode, self.limit) {
+            effects.push(Box::new(Limiter::new(
+                fs,
+                channels,
+                &LimiterSettings::new(
+                    self.ceiling_db,
+                    self.lookahead_time,
+                    self.release_time,
+                ),
+            )?));
+        }
+
+        Ok(effects)
+    }
+
+    /// Measures the loudness statistics and the true peak of the input
+    /// in linear units, e.g. to verify the normalized output.
+    pub fn measure<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+    ) -> Result<(Vec<Statistics>, Vec<f64>), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let analyzer = Lufs::new(self.channel_independent, self.strict_ebur128);
+        let loudness = analyzer.analyze_statistics(input, layout)?;
         input.seek(0)?;
-        let amplify =
-            Amplify::new(gain.iter().map(|x| 20.0 * x.log10()).collect());
-        amplify.amplify(&mut input, &mut output)?;
+        let analyzer = TruePeak::new(self.channel_independent);
+        let true_peak = analyzer.analyze(input)?;
+        input.seek(0)?;
+
+        Ok((loudness, true_peak))
+    }
+}
+
+/// Frame based level measurement for normalization.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    /// Number of input channels
+    channels: usize,
+    /// Analyze multiple channels independently
+    channel_independent: bool,
+    /// Normalization factor of the loudness to stereo
+    loudness_norm: f64,
+    /// Loudness analyzers, empty if not required by the mode. None for
+    /// the LFE of independent channels.
+    loudness: Vec<Option<Loudness>>,
+    /// RMS analyzers, empty if not required by the mode
+    rms: Vec<Rms>,
+    /// True peak analyzers, empty if not required by the mode
+    true_peak: Vec<TruePeakAnalyzer>,
+}
+
+impl Measurement {
+    pub fn process(&mut self, frame: &[f32]) -> Result<(), Error> {
+        if frame.len() != self.channels {
+            return Err(Error::InvalidFrame);
+        }
+
+        if self.channel_independent {
+            for (i, x) in frame.iter().enumerate() {
+                let x = vec![*x];
+                if let Some(Some(a)) = self.loudness.get_mut(i) {
+                    a.process(&x)?;
+                }
+                if let Some(a) = self.rms.get_mut(i) {
+                    a.process(&x)?;
+                }
+                if let Some(a) = self.true_peak.get_mut(i) {
+                    a.process(&x)?;
+                }
+            }
+        } else {
+            let frame = frame.to_vec();
+            for a in self.loudness.iter_mut().flatten() {
+                a.process(&frame)?;
+            }
+            for a in self.rms.iter_mut() {
+                a.process(&frame)?;
+            }
+            for a in self.true_peak.iter_mut() {
+                a.process(&frame)?;
+            }
+        }
 
         Ok(())
     }
+
+    /// Returns the integrative loudness, RMS and true peak levels
+    /// in linear units. The loudness of an unmeasured LFE is None.
+    #[allow(clippy::type_complexity)]
+    fn finalize(
+        mut self,
+    ) -> Result<(Vec<Option<f64>>, Vec<f64>, Vec<f64>), Error> {
+        let loudness = self
+            .loudness
+            .iter_mut()
+            .map(|x| match x {
+                Some(x) => {
+                    x.finalize()?;
+                    Ok(Some(x.integrative_loudness() * self.loudness_norm))
+                }
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<Option<f64>>, Error>>()?;
+        let rms = self.rms.iter().map(|x| x.rms()).collect();
+        let true_peak = self.true_peak.iter().map(|x| x.true_peak()).collect();
+
+        Ok((loudness, rms, true_peak))
+    }
+}
+
+#[test]
+fn test_normalize_lufs_true_peak() {
+    use crate::conversion::{Dither, FrameWriter};
+    use crate::generator::{Generator, Signal};
+
+    let fs = 48000.0;
+    let layout = ChannelMap::default_layout(2);
+    for limit in [false, true] {
+        let mut input =
+            Generator::new(&Signal::PinkNoise, fs, 2, -30.0, 240000)
+                .unwrap()
+                .into_wav();
+        let settings = Settings {
+            mode: Mode::LufsTruePeak,
+            target_db: -10.0,
+            channel_independent: false,
+            strict_ebur128: false,
+            ceiling_db: -1.0,
+            limit,
+            lookahead_time: default_lookahead_time(),
+            release_time: default_release_time(),
+        };
+
+        let mut output = std::io::Cursor::new(Vec::new());
+        let spec = hound::WavSpec {
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+            ..input.spec()
+        };
+        let writer = hound::WavWriter::new(&mut output, spec).unwrap();
+        let mut writer = FrameWriter::new(writer, Dither::None);
+        settings
+            .normalize(&mut input, &mut writer, &layout)
+            .unwrap();
+        writer.finalize().unwrap();
+
+        output.set_position(0);
+        let mut output = WavReader::new(output).unwrap();
+        let (loudness, true_peak) =
+            settings.measure(&mut output, &layout).unwrap();
+        let loudness = 10.0 * loudness[0].integrative.log10();
+        let true_peak = 20.0 * true_peak[0].log10();
+        assert!(true_peak <= -1.0 + 0.01, "{} {}", limit, true_peak);
+        if limit {
+            // The limiter only touches the peaks, the loudness is kept.
+            assert!((loudness + 10.0).abs() < 0.1, "{}", loudness);
+            assert!(true_peak > -1.5);
+        } else {
+            // The gain is reduced until the true peak hits the ceiling.
+            assert!(loudness < -10.5, "{}", loudness);
+            assert!((true_peak + 1.0).abs() < 0.01);
+        }
+    }
+}
+
+#[test]
+fn test_normalize_channel_independent() {
+    use crate::generator::{Generator, Signal};
+
+    // 5.1 sine at -20 dBFS, every channel is normalized to -23 LUFS with
+    // its BS.1770 weight and the LFE keeps its level.
+    let fs = 48000.0;
+    let layout = ChannelMap::default_layout(6);
+    let settings = Settings {
+        mode: Mode::Lufs,
+        target_db: -23.0,
+        channel_independent: true,
+        strict_ebur128: false,
+        ceiling_db: default_ceiling_db(),
+        limit: false,
+        lookahead_time: default_lookahead_time(),
+        release_time: default_release_time(),
+    };
+    let mut measurement = settings.measurement(fs, &layout);
+    let signal = Signal::Sine {
+        frequency: 1000.0,
+        phase: 0.0,
+    };
+    for frame in Generator::new(&signal, fs, 6, -20.0, 96000).unwrap() {
+        measurement.process(&frame).unwrap();
+    }
+    let gain = settings.gain_db(measurement).unwrap();
+    let expected = [0.01, 0.01, 0.01, 0.0, -1.48, -1.48];
+    assert_eq!(gain.len(), expected.len());
+    for (x, y) in gain.iter().zip(expected) {
+        assert!((x - y).abs() < 0.1, "{} {}", x, y);
+    }
+    assert_eq!(gain[3], 0.0);
 }
@@ -0,0 +1,92 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::conversion::{Conversion, FrameWriter};
+use crate::error::Error;
+use crate::filters::resampler::{Quality, Resampler};
+use crate::frame::FrameIterator;
+use crate::progress::Progress;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct Settings {
+    /// Output sample rate in Hz
+    sample_rate: u32,
+    /// Resampling filter quality
+    #[arg(short, long, value_enum, default_value_t = Quality::High)]
+    quality: Quality,
+}
+
+impl Settings {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn resample<R, W>(
+        &self,
+        input: &mut WavReader<R>,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let duration = input.duration() as usize;
+        let mut resampler = Resampler::new(
+            spec.sample_rate,
+            self.sample_rate,
+            spec.channels as usize,
+            self.quality,
+        )?;
+
+        let length = resampler.output_len(duration);
+        let mut skip = resampler.latency();
+        let mut written = 0;
+        // Returns true once the output is complete.
+        let mut write_frames = |frames: Vec<Vec<f32>>| -> Result<bool, Error> {
+            for frame in frames {
+                // Compensate filter latency
+                if skip > 0 {
+                    skip -= 1;
+                } else if written < length {
+                    output.write_frame(&frame)?;
+                    written += 1;
+                }
+            }
+            Ok(written == length)
+        };
+
+        let mut progress = Progress::new(duration, "Resampling sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        while let Some(frame) = frames.next() {
+            progress.next();
+            match frame {
+                Ok(frame) => {
+                    write_frames(resampler.process(frame)?)?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // Drain the filter until the output has the resampled length.
+        let padding = vec![0.0; spec.channels as usize];
+        while !write_frames(resampler.process(&padding)?)? {}
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+use crate::analyzer::silence::{self, Region};
+use crate::conversion::{Conversion, FrameWriter};
+use crate::envelope::Position;
+use crate::error::Error;
+use crate::frame::{ChannelMap, FrameIterator};
+use crate::progress::Progress;
+use crate::report::Format;
+use hound::WavReader;
+
+#[derive(Debug, Clone, clap::Args)]
+#[group(skip)]
+pub struct Settings {
+    #[command(flatten)]
+    silence: silence::Settings,
+    /// Silence to keep before the audio in seconds or with unit suffix
+    /// s, ms or samples
+    #[arg(long, default_value = "0")]
+    pre_padding: Position,
+    /// Silence to keep after the audio
+    #[arg(long, default_value = "0")]
+    post_padding: Position,
+    /// Write the silent regions as cue list in the report format to
+    /// this file
+    #[arg(long)]
+    cues: Option<String>,
+}
+
+impl Settings {
+    /// Finds the silent regions of the input and writes the cue list.
+    fn analyze<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+        format: Format,
+    ) -> Result<Vec<Region>, Error>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let regions = self.silence.analyze(input, layout)?;
+        input.seek(0)?;
+        if let Some(filename) = &self.cues {
+            let mut output =
+                std::io::BufWriter::new(std::fs::File::create(filename)?);
+            silence::Settings::write(
+                &regions,
+                input.spec().sample_rate as f64,
+                format,
+                &mut output,
+            )?;
+        }
+
+        Ok(regions)
+    }
+
+    /// Extends the audio region by the padding.
+    fn pad(&self, region: Region, fs: f64, duration: usize) -> Region {
+        Region {
+            start: region.start.saturating_sub(self.pre_padding.samples(fs)),
+            end: (region.end + self.post_padding.samples(fs)).min(duration),
+        }
+    }
+
+    /// Returns the audio region without leading and trailing silence, or
+    /// no region if the input is silent.
+    pub fn trim_region<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+        format: Format,
+    ) -> Result<Vec<Region>, Error>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let duration = input.duration() as usize;
+        let regions = self.analyze(input, layout, format)?;
+        let mut audio = Region {
+            start: 0,
+            end: duration,
+        };
+        if let Some(x) = regions.first().filter(|x| x.start == 0) {
+            audio.start = x.end;
+        }
+        if let Some(x) = regions.last().filter(|x| x.end == duration) {
+            audio.end = x.start;
+        }
+        if audio.start >= audio.end {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![self.pad(
+            audio,
+            input.spec().sample_rate as f64,
+            duration,
+        )])
+    }
+
+    /// Returns the audio regions between the silent regions.
+    pub fn split_regions<R>(
+        &self,
+        input: &mut WavReader<R>,
+        layout: &[ChannelMap],
+        format: Format,
+    ) -> Result<Vec<Region>, Error>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let fs = input.spec().sample_rate as f64;
+        let duration = input.duration() as usize;
+        let mut start = 0;
+        let mut audio = Vec::new();
+        for x in self.analyze(input, layout, format)? {
+            if x.start > start {
+                audio.push(self.pad(
+                    Region {
+                        start,
+                        end: x.start,
+                    },
+                    fs,
+                    duration,
+                ));
+            }
+            start = x.end;
+        }
+        if start < duration {
+            audio.push(self.pad(
+                Region {
+                    start,
+                    end: duration,
+                },
+                fs,
+                duration,
+            ));
+        }
+
+        Ok(audio)
+    }
+
+    /// Writes one region of the input to the output.
+    pub fn write<R, W>(
+        input: &mut WavReader<R>,
+        region: Region,
+        output: &mut FrameWriter<W>,
+    ) -> Result<(), Error>
+    where
+        R: std::io::Read + std::io::Seek,
+        W: std::io::Write + std::io::Seek,
+    {
+        let spec = input.spec();
+        let length = region.end - region.start;
+        input.seek(region.start as u32)?;
+        let mut progress = Progress::new(length, "Writing sample");
+        let mut frames = FrameIterator::new(input.samples_f32(), spec.channels);
+        for _ in 0..length {
+            progress.next();
+            match frames.next() {
+                Some(frame) => output.write_frame(frame?)?,
+                None => return Err(Error::InvalidFrame),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_trim_split() {
+    use crate::analyzer::silence::test_input;
+    use crate::conversion::Dither;
+
+    let layout = [ChannelMap::Left];
+    let settings = |min_duration: &str,
+                    pre_padding: &str,
+                    post_padding: &str| Settings {
+        silence: silence::Settings::new(-60.0, min_duration.parse().unwrap()),
+        pre_padding: pre_padding.parse().unwrap(),
+        post_padding: post_padding.parse().unwrap(),
+        cues: None,
+    };
+    let trim = |settings: &Settings, audio: &[std::ops::Range<usize>]| {
+        let mut input = test_input(audio, 1000);
+        settings
+            .trim_region(&mut input, &layout, Format::Text)
+            .unwrap()
+    };
+    let split = |settings: &Settings| {
+        let mut input = test_input(&[100..300, 320..900], 1000);
+        settings
+            .split_regions(&mut input, &layout, Format::Text)
+            .unwrap()
+    };
+
+    // Audio from 100 to 300, a short pause until 320 and audio until 900.
+    let audio = [100..300, 320..900];
+    assert_eq!(
+        trim(&settings("50ms", "0", "0"), &audio),
+        [Region {
+            start: 100,
+            end: 900
+        }]
+    );
+    // The padding is limited by the input.
+    assert_eq!(
+        trim(&settings("50ms", "20ms", "200ms"), &audio),
+        [Region {
+            start: 80,
+            end: 1000
+        }]
+    );
+    assert_eq!(trim(&settings("50ms", "0", "0"), &[]), []);
+
+    // The short pause only splits with a shorter minimum duration.
+    assert_eq!(
+        split(&settings("50ms", "0", "0")),
+        [Region {
+            start: 100,
+            end: 900
+        }]
+    );
+    assert_eq!(
+        split(&settings("10ms", "5ms", "5ms")),
+        [
+            Region {
+                start: 95,
+                end: 305
+            },
+            Region {
+                start: 315,
+                end: 905
+            }
+        ]
+    );
+
+    let mut input = test_input(&audio, 1000);
+    let mut output = std::io::Cursor::new(Vec::new());
+    let writer = hound::WavWriter::new(&mut output, input.spec()).unwrap();
+    let mut writer = FrameWriter::new(writer, Dither::None);
+    let region = Region {
+        start: 90,
+        end: 310,
+    };
+    Settings::write(&mut input, region, &mut writer).unwrap();
+    writer.finalize().unwrap();
+    output.set_position(0);
+    let output: Vec<f32> = WavReader::new(output)
+        .unwrap()
+        .samples::<f32>()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(output.len(), 220);
+    assert!(output[..10].iter().all(|x| *x == 0.0));
+    assert!(output[10..210].iter().all(|x| *x == 0.5));
+    assert!(output[210..].iter().all(|x| *x == 0.0));
+}
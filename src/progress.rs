@@ -31,7 +31,8 @@ impl Progress {
         Self {
             count: 0,
             total_count,
-            update_every: total_count / 100,
+            // Short inputs are updated every sample.
+            update_every: (total_count / 100).max(1),
             message: message.into(),
         }
     }
@@ -49,3 +50,13 @@ impl Drop for Progress {
         eprintln!();
     }
 }
+
+#[test]
+fn test_progress_short_input() {
+    // Less than 100 samples must not divide by zero.
+    let mut progress = Progress::new(10, "Testing sample");
+    for _ in 0..10 {
+        progress.next();
+    }
+    assert_eq!(progress.update_every, 1);
+}
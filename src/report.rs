@@ -0,0 +1,225 @@
+/******************************************************************************\
+    wavehacker
+    Copyright (C) 2023 Max Maisel
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+\******************************************************************************/
+//! Machine-readable analysis reports.
+//!
+//! Every report contains the input file name, sample rate in Hz,
+//! channel count, duration in seconds and a list of results. Each result
+//! has a quantity name, a dB based unit and one value per analyzed channel.
+//! Channels are named "ch1", "ch2", ... if they were analyzed independently
+//! and "mix" if all channels were analyzed together. Values of silent
+//! input are -inf dB which is written as null in JSON reports.
+//!
+//! CSV reports contain one row per result value with the columns
+//! "file,sample_rate,channels,duration,quantity,unit,channel,value".
+use crate::error::Error;
+use hound::WavReader;
+
+/// Report output formats.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    /// Human readable text
+    Text,
+    /// Comma separated values
+    Csv,
+    /// JSON document
+    Json,
+}
+
+/// Result of one analyzed quantity.
+#[derive(Debug, serde::Serialize)]
+pub struct Entry {
+    /// Quantity name, e.g. "true-peak".
+    quantity: &'static str,
+    /// Unit of the values, e.g. "dBTP".
+    unit: &'static str,
+    /// Channel names, one for each value.
+    channels: Vec<String>,
+    /// Analyzed values.
+    values: Vec<f64>,
+}
+
+/// Analysis report of one input file.
+#[derive(Debug, serde::Serialize)]
+pub struct Report {
+    /// Input file name
+    file: String,
+    /// Sample rate in Hz
+    sample_rate: u32,
+    /// Number of channels in the input file
+    channels: u16,
+    /// Input duration in seconds
+    duration: f64,
+    /// Analysis results
+    results: Vec<Entry>,
+}
+
+impl Report {
+    pub fn new<R>(file: &str, input: &WavReader<R>) -> Self
+    where
+        R: std::io::Read,
+    {
+        let spec = input.spec();
+        Self {
+            file: file.into(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            duration: input.duration() as f64 / spec.sample_rate as f64,
+            results: Vec::new(),
+        }
+    }
+
+    /// Adds the values of one quantity in dB units to the report.
+    pub fn push(
+        &mut self,
+        quantity: &'static str,
+        unit: &'static str,
+        values: Vec<f64>,
+    ) {
+        let channels = if values.len() == 1 && self.channels > 1 {
+            vec!["mix".into()]
+        } else {
+            (1..=values.len()).map(|x| format!("ch{}", x)).collect()
+        };
+        self.push_channels(quantity, unit, channels, values);
+    }
+
+    /// Adds the values of one quantity with explicit channel names,
+    /// e.g. if some channels were not analyzed.
+    pub fn push_channels(
+        &mut self,
+        quantity: &'static str,
+        unit: &'static str,
+        channels: Vec<String>,
+        values: Vec<f64>,
+    ) {
+        self.results.push(Entry {
+            quantity,
+            unit,
+            channels,
+            values,
+        });
+    }
+
+    /// Writes the report in the selected format.
+    pub fn write<W>(&self, format: Format, output: &mut W) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        match format {
+            Format::Text => {
+                writeln!(output, "File: {}", self.file)?;
+                writeln!(output, "Sample rate: {} Hz", self.sample_rate)?;
+                writeln!(output, "Channels: {}", self.channels)?;
+                writeln!(output, "Duration: {:.3} s", self.duration)?;
+                for entry in &self.results {
+                    let values = entry
+                        .channels
+                        .iter()
+                        .zip(entry.values.iter())
+                        .map(|(c, x)| {
+                            format!("{:.2} {} ({})", x, entry.unit, c)
+                        })
+                        .collect::<Vec<String>>();
+                    writeln!(
+                        output,
+                        "{}: {}",
+                        entry.quantity,
+                        values.join(", ")
+                    )?;
+                }
+            }
+            Format::Csv => {
+                writeln!(
+                    output,
+                    "file,sample_rate,channels,duration,quantity,unit,channel,value"
+                )?;
+                for entry in &self.results {
+                    for (channel, value) in
+                        entry.channels.iter().zip(entry.values.iter())
+                    {
+                        writeln!(
+                            output,
+                            "\"{}\",{},{},{},{},{},{},{}",
+                            self.file.replace('"', "\"\""),
+                            self.sample_rate,
+                            self.channels,
+                            self.duration,
+                            entry.quantity,
+                            entry.unit,
+                            channel,
+                            value,
+                        )?;
+                    }
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *output, self)?;
+                writeln!(output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_report() {
+    use crate::generator::{Generator, Signal};
+
+    let wav = Generator::new(&Signal::Silence, 48000.0, 2, 0.0, 24000)
+        .unwrap()
+        .into_wav();
+    let mut report = Report::new("a \"b\".wav", &wav);
+    report.push("true-peak", "dBTP", vec![-1.0, f64::NEG_INFINITY]);
+    report.push("integrative-loudness", "LUFS", vec![-23.0]);
+    let write = |format| {
+        let mut output = Vec::new();
+        report.write(format, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    let text = write(Format::Text);
+    assert!(text.starts_with("File: a \"b\".wav\nSample rate: 48000 Hz\n"));
+    assert!(text.contains("Duration: 0.500 s\n"));
+    assert!(text.contains("true-peak: -1.00 dBTP (ch1), -inf dBTP (ch2)\n"));
+    assert!(text.contains("integrative-loudness: -23.00 LUFS (mix)\n"));
+
+    let csv = write(Format::Csv);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "file,sample_rate,channels,duration,quantity,unit,channel,value",
+            "\"a \"\"b\"\".wav\",48000,2,0.5,true-peak,dBTP,ch1,-1",
+            "\"a \"\"b\"\".wav\",48000,2,0.5,true-peak,dBTP,ch2,-inf",
+            "\"a \"\"b\"\".wav\",48000,2,0.5,integrative-loudness,LUFS,mix,-23",
+        ]
+    );
+
+    let json: serde_json::Value =
+        serde_json::from_str(&write(Format::Json)).unwrap();
+    assert_eq!(json["file"], "a \"b\".wav");
+    assert_eq!(json["sample_rate"], 48000);
+    assert_eq!(json["channels"], 2);
+    assert_eq!(json["duration"], 0.5);
+    assert_eq!(json["results"][0]["quantity"], "true-peak");
+    assert_eq!(json["results"][0]["unit"], "dBTP");
+    assert_eq!(json["results"][0]["channels"][1], "ch2");
+    assert_eq!(json["results"][0]["values"][0], -1.0);
+    assert!(json["results"][0]["values"][1].is_null());
+    assert_eq!(json["results"][1]["channels"][0], "mix");
+}